@@ -0,0 +1,28 @@
+#!/bin/sh
+# Fetches the community chip-8-database files vendored here. They are taken from
+# the chip8_db crate, which bundles the database as a git submodule, so the crate
+# version below pins one upstream commit and the checksum guards the download.
+#
+#     db/update.sh
+#
+# To move to a newer release, change VERSION and SHA256 (the "cksum" field of
+# https://index.crates.io/ch/ip/chip8_db) and note the new version in README.md.
+set -eu
+
+VERSION=2.1.0
+SHA256=1f9070a0940795948ae096391313821a37c2a0d31edc46486b433e5543bb3b2b
+url=https://static.crates.io/crates/chip8_db/chip8_db-$VERSION.crate
+cd "$(dirname "$0")"
+
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+curl -fsSL "$url" -o "$tmp/crate.tar.gz"
+echo "$SHA256  $tmp/crate.tar.gz" | sha256sum -c - >/dev/null
+tar -xzf "$tmp/crate.tar.gz" -C "$tmp"
+
+src=$tmp/chip8_db-$VERSION/chip-8-database
+for file in programs.json sha1-hashes.json platforms.json; do
+    cp "$src/database/$file" "$file"
+done
+cp "$src/LICENSE.md" LICENSE.md
+echo "Updated from chip8_db $VERSION, run cargo test to check the bundled ROMs are still found."
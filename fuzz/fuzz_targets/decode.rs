@@ -0,0 +1,23 @@
+#![no_main]
+// Every pair of bytes decodes to something that prints and encodes back to the
+// same word, bar the 5XYN and 9XYN aliases, and strict decoding agrees with it on
+// valid instructions.
+use chip8::OpCode;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for pair in data.chunks_exact(2) {
+        let word = u16::from_be_bytes([pair[0], pair[1]]);
+        let op = OpCode::from_bytes((pair[0], pair[1]));
+        let _ = op.to_string();
+        let alias = matches!(word >> 12, 0x5 | 0x9) && word & 0xF != 0;
+        match OpCode::try_from(word) {
+            Ok(strict) => assert_eq!(strict, op),
+            Err(_) if alias => assert_eq!(op.encode(), word & 0xFFF0),
+            Err(_) => assert_eq!(op, OpCode::Unknown(word)),
+        }
+        if !alias {
+            assert_eq!(op.encode(), word);
+        }
+    }
+});
@@ -0,0 +1,34 @@
+#![no_main]
+// The input is loaded as a ROM and run headlessly for a second of emulated time
+// under two very different configurations. Faults and ROMs too large to load are
+// fine, panics are not.
+use chip8::database::Rom;
+use chip8::{MemoryMode, Platform, Timing, VM};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 60;
+
+fn run(rom: &[u8], platform: Platform, timing: Timing, memory: MemoryMode) {
+    let mut vm = VM::new();
+    vm.set_quirks(platform.quirks());
+    vm.set_timing(timing);
+    vm.set_memory_mode(memory);
+    if vm.load_rom(&Rom::new(rom.to_vec())).is_err() {
+        return;
+    }
+
+    for frame in 0..FRAMES {
+        // hold each key in turn so key waits and skips take both branches
+        for key in 0..16 {
+            vm.set_key(key, u32::from(key) == frame % 16);
+        }
+        if vm.run_frame().is_err() {
+            break;
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    run(data, Platform::Chip8, Timing::Vip, MemoryMode::Strict);
+    run(data, Platform::XoChip, Timing::Fixed(100), MemoryMode::Wrap64K);
+});
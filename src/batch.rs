@@ -0,0 +1,259 @@
+// Runs many ROMs headlessly and reports how each one ended, for `chip8-batch`.
+// A report from one emulator version can be diffed against the next: rows are
+// sorted by path and everything but the timing column is deterministic.
+use crate::config::{Config, ConfigLayer};
+use crate::database::sha1_hex;
+use crate::patch;
+use crate::{SCREEN_WIDTH, VM};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Outcome {
+    pub rom: String,
+    pub sha1: String,
+    pub status: &'static str, // "ok", "fault", "panic", or "error" when the ROM couldn't be loaded
+    pub detail: String,       // the fault, panic or load error
+    pub pc: u16,
+    pub frames: u32,
+    pub instructions: u64,
+    pub screen: String, // SHA-1 of the final framebuffer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub millis: Option<f64>,
+}
+
+const CSV_HEADER: &str = "rom,sha1,status,detail,pc,frames,instructions,screen,millis";
+
+// ROM files in `paths`, looking inside directories, sorted so reports line up
+pub fn collect(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut roms = vec![];
+    let mut pending = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?.path();
+                let rom = entry
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("ch8") || e.eq_ignore_ascii_case("c8"));
+                if rom || entry.is_dir() {
+                    pending.push(entry);
+                }
+            }
+        } else {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+// Runs `path` for `frames` frames, or until it faults. Load errors and panics are
+// reported like faults so one bad ROM doesn't end the batch.
+#[must_use]
+pub fn run(path: &Path, frames: u32, cli: &ConfigLayer) -> Outcome {
+    let mut outcome = Outcome {
+        rom: path.display().to_string(),
+        sha1: String::new(),
+        status: "ok",
+        detail: String::new(),
+        pc: 0,
+        frames: 0,
+        instructions: 0,
+        screen: String::new(),
+        millis: None,
+    };
+    // with its sidecar patches, as the other frontends run it; the hash stays the
+    // unpatched file's
+    let rom = match patch::load(path, &[]) {
+        Ok(rom) => rom,
+        Err(e) => {
+            outcome.status = "error";
+            outcome.detail = e.to_string();
+            return outcome;
+        }
+    };
+    outcome.sha1.clone_from(&rom.sha1);
+    let config = match Config::resolve(&rom, path, cli) {
+        Ok(config) => config,
+        Err(e) => {
+            outcome.status = "error";
+            outcome.detail = e.to_string();
+            return outcome;
+        }
+    };
+
+    let mut vm = VM::from_config(&config);
+    if let Err(e) = vm.load_rom(&rom) {
+        outcome.status = "error";
+        outcome.detail = e.to_string();
+        return outcome;
+    }
+
+    let start = Instant::now();
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut instructions = 0;
+        let mut count = |_: &mut VM| {
+            instructions += 1;
+            false
+        };
+        let mut fault = None;
+        let mut ran = 0;
+        while ran < frames {
+            ran += 1;
+            if let Err(f) = vm.run_frame_until(&mut count) {
+                fault = Some(f);
+                break;
+            }
+        }
+        (vm, ran, instructions, fault)
+    }));
+    outcome.millis = Some(start.elapsed().as_secs_f64() * 1000.0);
+
+    match run {
+        Ok((vm, ran, instructions, fault)) => {
+            if let Some(fault) = fault {
+                outcome.status = "fault";
+                outcome.detail = fault.to_string();
+            }
+            outcome.pc = vm.program_counter();
+            outcome.frames = ran;
+            outcome.instructions = instructions;
+            outcome.screen = screen_hash(&vm);
+        }
+        Err(panic) => {
+            outcome.status = "panic";
+            outcome.detail = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(ToString::to_string))
+                .unwrap_or_default();
+        }
+    }
+    outcome
+}
+
+// only the visible bytes of each row, so the hash doesn't depend on Row's width
+fn screen_hash(vm: &VM) -> String {
+    let bytes: Vec<u8> = vm
+        .framebuffer()
+        .rows()
+        .iter()
+        .flat_map(|row| row.to_be_bytes()[..SCREEN_WIDTH as usize / 8].to_vec())
+        .collect();
+    sha1_hex(&bytes)
+}
+
+// runs every ROM on `threads` worker threads, returning outcomes in `roms` order
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn run_all(roms: &[PathBuf], frames: u32, cli: &ConfigLayer, threads: usize) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(vec![None; roms.len()]);
+    std::thread::scope(|s| {
+        for _ in 0..threads.clamp(1, roms.len().max(1)) {
+            s.spawn(|| loop {
+                let n = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = roms.get(n) else { break };
+                let outcome = run(path, frames, cli);
+                outcomes.lock().unwrap()[n] = Some(outcome);
+            });
+        }
+    });
+    outcomes
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+pub fn write_json<W: Write>(writer: W, outcomes: &[Outcome]) -> io::Result<()> {
+    serde_json::to_writer_pretty(writer, outcomes).map_err(io::Error::other)
+}
+
+pub fn write_csv<W: Write>(mut writer: W, outcomes: &[Outcome]) -> io::Result<()> {
+    writeln!(writer, "{CSV_HEADER}")?;
+    for o in outcomes {
+        writeln!(
+            writer,
+            "{},{},{},{},{:#06X},{},{},{},{}",
+            csv_field(&o.rom),
+            o.sha1,
+            o.status,
+            csv_field(&o.detail),
+            o.pc,
+            o.frames,
+            o.instructions,
+            o.screen,
+            o.millis.map(|m| format!("{m:.3}")).unwrap_or_default(),
+        )?;
+    }
+    Ok(())
+}
+
+// quoted when it holds a comma, quote or newline, with quotes doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect, run_all, write_csv};
+    use crate::config::ConfigLayer;
+    use crate::database::sha1_hex;
+
+    #[test]
+    fn test_batch() {
+        let dir = std::env::temp_dir().join(format!("chip8-batch-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("more")).unwrap();
+        // one that draws the font's 0 and spins, a return with nothing on the stack,
+        // and an unrelated file that isn't picked up
+        std::fs::write(dir.join("draw.ch8"), [0xD0, 0x05, 0x12, 0x02]).unwrap();
+        std::fs::write(dir.join("more/crash, again.ch8"), [0x00, 0xE0, 0x00, 0xEE]).unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        // a crash that its IPS sidecar turns into a loop
+        std::fs::write(dir.join("patched.ch8"), [0x00, 0xEE]).unwrap();
+        std::fs::write(dir.join("patched.ch8.ips"), b"PATCH\0\0\0\0\x02\x12\x00EOF").unwrap();
+
+        let roms = collect(&[dir.clone(), dir.join("missing.ch8")]).unwrap();
+        assert_eq!(roms.len(), 4);
+        let outcomes = run_all(&roms, 10, &ConfigLayer::default(), 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let draw = &outcomes[0];
+        assert_eq!((draw.status, draw.frames), ("ok", 10));
+        assert!(draw.instructions > 10);
+        let crash = &outcomes[2];
+        assert_eq!(
+            (crash.status, crash.frames, crash.instructions),
+            ("fault", 1, 2)
+        );
+        assert_eq!(crash.detail, "return with an empty stack");
+        assert_ne!(crash.screen, draw.screen);
+        assert_eq!(outcomes[1].status, "error");
+        let patched = &outcomes[3];
+        assert_eq!((patched.status, patched.frames), ("ok", 10));
+        assert_eq!(patched.sha1, sha1_hex(&[0x00, 0xEE]));
+
+        let mut outcomes = outcomes;
+        for o in &mut outcomes {
+            o.millis = None;
+        }
+        let mut out = vec![];
+        write_csv(&mut out, &outcomes).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let line = csv.lines().nth(3).unwrap();
+        assert!(line.starts_with('"') && line.contains("/more/crash, again.ch8\","));
+        assert!(line.ends_with(
+            ",fault,return with an empty stack,0x0202,1,2,b376885ac8452b6cbf9ced81b1080bfd570d9b91,"
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+// Runs a library of ROMs headlessly for a number of frames each, on several
+// threads, and writes how each one ended as JSON or CSV:
+//
+//     chip8-batch --frames 1200 --csv before.csv roms/
+//     chip8-batch --frames 1200 --csv after.csv --no-timing roms/
+//
+// Each row has the ROM's SHA-1, whether it ran to the end, faulted (unknown
+// opcode, stack over- or underflow, bad address) or couldn't be loaded, where it
+// stopped, the instructions it ran and a hash of the final screen. Leave out the
+// timing with `--no-timing` to get reports that diff cleanly between versions.
+use chip8::batch::{collect, run_all, write_csv, write_json};
+use chip8::config::ConfigLayer;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+fn main() -> std::io::Result<()> {
+    let mut paths = vec![];
+    let mut frames = 600;
+    let mut threads = std::thread::available_parallelism().map_or(1, usize::from);
+    let mut json = None;
+    let mut csv = None;
+    let mut timing = true;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().and_then(|n| n.parse().ok()).unwrap_or(frames),
+            "--threads" => threads = args.next().and_then(|n| n.parse().ok()).unwrap_or(threads),
+            "--json" => json = args.next(),
+            "--csv" => csv = args.next(),
+            "--no-timing" => timing = false,
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        println!("Usage: chip8-batch [--frames N] [--threads N] [--json FILE] [--csv FILE] [--no-timing] [--ipf N | --vip] [--platform P] [--memory M] ROM|DIR...");
+        return Ok(());
+    }
+
+    let roms = collect(&paths)?;
+    let mut outcomes = run_all(&roms, frames, &cli, threads);
+    if !timing {
+        for o in &mut outcomes {
+            o.millis = None;
+        }
+    }
+
+    if let Some(file) = &json {
+        write_json(BufWriter::new(std::fs::File::create(file)?), &outcomes)?;
+    }
+    if let Some(file) = &csv {
+        write_csv(BufWriter::new(std::fs::File::create(file)?), &outcomes)?;
+    }
+    if json.is_none() && csv.is_none() {
+        let mut out = std::io::stdout().lock();
+        write_csv(&mut out, &outcomes)?;
+        out.flush()?;
+    }
+
+    let failed = outcomes.iter().filter(|o| o.status != "ok").count();
+    eprintln!("{} ROMs, {failed} failed", outcomes.len());
+    Ok(())
+}
@@ -0,0 +1,40 @@
+// Debug adapter for editors speaking the Debug Adapter Protocol (see `chip8::dap`).
+// Talks over stdin and stdout by default, as editors launch adapters, or listens
+// on a localhost port for adapters started by hand:
+//
+//     chip8-dap --port 4711
+use chip8::config::ConfigLayer;
+use chip8::dap::DapServer;
+use std::net::TcpListener;
+
+fn main() -> std::io::Result<()> {
+    let mut port = None;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().and_then(|n| n.parse::<u16>().ok()),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => {
+                // stdout carries the protocol, so usage goes to stderr
+                eprintln!(
+                    "Usage: chip8-dap [--port N] [--ipf N | --vip] [--platform P] [--memory M]"
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    match port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            eprintln!("Waiting for a debugger on {}", listener.local_addr()?);
+            let (stream, _) = listener.accept()?;
+            DapServer::new(stream.try_clone()?, cli).run(stream)
+        }
+        None => DapServer::new(std::io::stdout(), cli).run(std::io::stdin()),
+    }
+}
@@ -0,0 +1,59 @@
+// Runs a ROM headlessly under the control of gdb, or any other client of the GDB
+// remote serial protocol, listening on localhost (see `chip8::gdb`):
+//
+//     chip8-gdb --port 1234 roms/brix.ch8
+//     gdb -ex 'target remote localhost:1234'
+//
+// Labels from `--symbols FILE`, or the ROM's `.sym` sidecar, are available
+// through `monitor break LABEL` and `monitor where`. Cheats for the ROM are loaded
+// as in the window frontend and managed with `monitor cheat` and `monitor search`.
+use chip8::config::{Config, ConfigLayer};
+use chip8::gdb::GdbServer;
+use chip8::patch;
+use chip8::symbols::Symbols;
+use chip8::{Cheats, VM};
+use std::net::TcpListener;
+
+fn main() -> std::io::Result<()> {
+    let mut rom = None;
+    let mut port = 1234;
+    let mut symbols = None;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().and_then(|n| n.parse().ok()).unwrap_or(port),
+            "--symbols" => symbols = args.next(),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(path) = rom else {
+        println!("Usage: chip8-gdb [--port N] [--symbols FILE] [--ipf N | --vip] [--platform P] [--memory M] ROM");
+        return Ok(());
+    };
+
+    let rom = patch::load(path.as_ref(), &[])?;
+    let mut vm = VM::from_config(&Config::resolve(&rom, path.as_ref(), &cli)?);
+    vm.load_rom(&rom)?;
+    vm.set_cheats(Cheats::for_rom(&rom, path.as_ref())?);
+    let symbols = Symbols::for_rom(path.as_ref(), symbols.as_deref())?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for gdb on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("Debugger connected from {peer}");
+    let mut server = GdbServer::new(vm);
+    if let Some(symbols) = symbols {
+        server.set_symbols(symbols);
+    }
+    server.run(stream)?;
+    println!(
+        "Debugger detached at {:#06X}",
+        server.vm().program_counter()
+    );
+    Ok(())
+}
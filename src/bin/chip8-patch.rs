@@ -0,0 +1,33 @@
+// Makes and applies IPS and BPS patches (see `chip8::patch`):
+//
+//     chip8-patch create game.ch8 fixed.ch8 game.ch8.ips
+//     chip8-patch apply game.ch8 translation.bps translated.ch8
+//
+// A patch saved next to the ROM as `game.ch8.ips` or `game.ch8.bps` is applied
+// whenever the ROM is loaded, so `apply` is only needed to share the result.
+use chip8::patch;
+use std::io;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (out, bytes) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["create", original, modified, out] => {
+            let original = std::fs::read(original)?;
+            let modified = std::fs::read(modified)?;
+            (out, patch::create_ips(&original, &modified))
+        }
+        ["apply", rom, patch, out] => {
+            let rom = std::fs::read(rom)?;
+            let bytes = patch::apply(&rom, &std::fs::read(patch)?);
+            (out, bytes.map_err(|e| format!("{patch}: {e}")))
+        }
+        _ => {
+            println!("Usage: chip8-patch create ORIGINAL MODIFIED OUT.ips | chip8-patch apply ROM PATCH OUT");
+            return Ok(());
+        }
+    };
+    let bytes = bytes.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    std::fs::write(out, &bytes)?;
+    println!("{} bytes written to {out}", bytes.len());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+// Runs a ROM headlessly with the profiler on, then prints where it spent its
+// instructions and optionally writes a folded stack file for flame graphs:
+//
+//     chip8-profile --frames 600 --folded brix.folded roms/brix.ch8
+//     flamegraph.pl brix.folded > brix.svg
+//
+// Coverage of the ROM can be written as an annotated listing, or as an lcov file
+// keyed by address, or by source line given a line map (see `chip8::linemap`):
+//
+//     chip8-profile --listing game.lst --lcov game.info --line-map game.map game.ch8
+//
+// Labels from `--symbols FILE`, or the ROM's `.sym` sidecar, replace addresses in
+// the report, folded stacks and listing (see `chip8::symbols`).
+use chip8::config::{Config, ConfigLayer};
+use chip8::linemap::LineMap;
+use chip8::patch;
+use chip8::symbols::Symbols;
+use chip8::VM;
+use std::io::BufWriter;
+
+fn main() -> std::io::Result<()> {
+    let mut rom = None;
+    let mut frames = 600;
+    let mut top = 20;
+    let mut folded = None;
+    let mut listing = None;
+    let mut lcov = None;
+    let mut line_map = None;
+    let mut source = None;
+    let mut symbols = None;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().and_then(|n| n.parse().ok()).unwrap_or(frames),
+            "--top" => top = args.next().and_then(|n| n.parse().ok()).unwrap_or(top),
+            "--folded" => folded = args.next(),
+            "--listing" => listing = args.next(),
+            "--lcov" => lcov = args.next(),
+            "--line-map" => line_map = args.next(),
+            "--source" => source = args.next(),
+            "--symbols" => symbols = args.next(),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(path) = rom else {
+        println!("Usage: chip8-profile [--frames N] [--top N] [--folded FILE] [--listing FILE] [--lcov FILE [--line-map FILE] [--source NAME]] [--symbols FILE] [--ipf N | --vip] [--platform P] [--memory M] ROM");
+        return Ok(());
+    };
+
+    let rom = patch::load(path.as_ref(), &[])?;
+    let mut vm = VM::from_config(&Config::resolve(&rom, path.as_ref(), &cli)?);
+    vm.load_rom(&rom)?;
+    let symbols = Symbols::for_rom(path.as_ref(), symbols.as_deref())?;
+    vm.enable_profiler();
+    if listing.is_some() || lcov.is_some() {
+        vm.enable_coverage();
+    }
+
+    for _ in 0..frames {
+        if let Err(fault) = vm.run_frame() {
+            println!("Stopped at {:#06X}: {fault}\n", vm.program_counter());
+            break;
+        }
+    }
+
+    let profile = vm.take_profiler().unwrap_or_default();
+    print!("{}", profile.report(top, symbols.as_ref()));
+    if let Some(file) = folded {
+        let out = BufWriter::new(std::fs::File::create(&file)?);
+        profile.write_folded(out, symbols.as_ref())?;
+        println!("\nFolded stacks written to {file}");
+    }
+
+    // the loaded ROM, which can reach the top of a 64K address space
+    let last = (0x200 + rom.bytes.len()).saturating_sub(1).min(0xFFFF);
+    let program = 0x200..=last as u16;
+    if let Some(coverage) = vm.take_coverage() {
+        if let Some(file) = listing {
+            let out = BufWriter::new(std::fs::File::create(&file)?);
+            coverage.write_listing(out, vm.memory(), program.clone(), symbols.as_ref())?;
+            println!("Coverage listing written to {file}");
+        }
+        if let Some(file) = lcov {
+            let map = line_map.as_ref().map(LineMap::read).transpose()?;
+            let source = source.unwrap_or(path);
+            let out = BufWriter::new(std::fs::File::create(&file)?);
+            coverage.write_lcov(out, &source, map.as_ref(), program)?;
+            println!("Coverage written to {file}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// Runs a ROM headlessly under a script (see `chip8::script`) until the script
+// stops or the frame limit is reached, for bots and automated checks:
+//
+//     chip8-script --frames 3600 checks/brix.rhai roms/brix.ch8
+//
+// Exits with status 1 when an assert failed, the script hit an error or the ROM
+// faulted. Labels in hooks come from `--symbols FILE` or the ROM's `.sym` sidecar.
+use chip8::config::{Config, ConfigLayer};
+use chip8::patch;
+use chip8::script::Script;
+use chip8::symbols::Symbols;
+use chip8::VM;
+
+fn main() -> std::io::Result<()> {
+    let mut files = vec![];
+    let mut frames = 3600;
+    let mut symbols = None;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().and_then(|n| n.parse().ok()).unwrap_or(frames),
+            "--symbols" => symbols = args.next(),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => files.push(arg),
+        }
+    }
+    let [script, path] = files.as_slice() else {
+        println!("Usage: chip8-script [--frames N] [--symbols FILE] [--ipf N | --vip] [--platform P] [--memory M] SCRIPT ROM");
+        return Ok(());
+    };
+
+    let rom = patch::load(path.as_ref(), &[])?;
+    let mut vm = VM::from_config(&Config::resolve(&rom, path.as_ref(), &cli)?);
+    vm.load_rom(&rom)?;
+    let symbols = Symbols::for_rom(path.as_ref(), symbols.as_deref())?;
+    let mut script = Script::read(script, symbols.as_ref())?;
+
+    let mut fault = None;
+    script.start(&mut vm);
+    loop {
+        for line in script.take_output() {
+            println!("{line}");
+        }
+        if fault.is_some() || script.is_stopped() || script.frame() >= frames {
+            break;
+        }
+        fault = script.run_frame(&mut vm).err();
+    }
+
+    if let Some(fault) = fault {
+        println!("ROM stopped at {:#06X}: {fault}", vm.program_counter());
+    }
+    for failure in script.failures() {
+        println!("FAILED {failure}");
+    }
+    println!(
+        "{} frames, {} failures",
+        script.frame(),
+        script.failures().len()
+    );
+    if fault.is_some() || !script.failures().is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use chip8::config::{config_dir, Config, ConfigLayer};
+use chip8::patch;
+use chip8::terminal::{render, Glyphs};
+use chip8::{Cheats, Timing, VM};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, queue, terminal};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+const INSTRUCTIONS_PER_FRAME: u32 = 11; // ~660 instructions per second, as this frontend always ran
+                                        // most terminals only report key presses (plus auto-repeat), so a key counts as held
+                                        // until this long after its last press unless the terminal also reports releases
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+// puts the terminal back the way we found it, even when bailing out with an error
+struct RawMode {
+    enhanced: bool,
+}
+
+impl RawMode {
+    fn enter() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = std::io::stdout();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Self { enhanced })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut out = std::io::stdout();
+        if self.enhanced {
+            let _ = execute!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let mut rom = None;
+    let mut glyphs = Glyphs::HalfBlock;
+    let mut patches = vec![];
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--braille" => glyphs = Glyphs::Braille,
+            "--patch" => patches.extend(args.next().map(std::path::PathBuf::from)),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--keymap" => cli.keymap = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(path) = rom else {
+        println!("Usage: chip8-term [--braille] [--patch FILE]... [--ipf N | --vip] [--platform P] [--keymap K] [--memory M] ROM");
+        return Ok(());
+    };
+
+    let rom = patch::load(path.as_ref(), &patches)?;
+    let defaults = Config {
+        timing: Timing::Fixed(INSTRUCTIONS_PER_FRAME),
+        ..Config::default()
+    };
+    let user_dir = config_dir();
+    let config = Config::resolve_with(defaults, user_dir.as_deref(), &rom, path.as_ref(), &cli)?;
+    let mut vm = VM::from_config(&config);
+    vm.load_rom(&rom)?;
+    vm.set_cheats(Cheats::for_rom(&rom, path.as_ref())?);
+
+    let raw = RawMode::enter()?;
+    let mut out = std::io::stdout();
+    let mut pressed: [Option<Instant>; 16] = [None; 16];
+    let mut redraw = true;
+    let mut beeping = false;
+    let mut fault = None;
+
+    'run: loop {
+        let start = Instant::now();
+
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            let quit = key.code == KeyCode::Esc
+                || (key.code == KeyCode::Char('c')
+                    && key.modifiers.contains(KeyModifiers::CONTROL));
+            if quit {
+                break 'run;
+            }
+            if let KeyCode::Char(c) = key.code {
+                if let Some(k) = vm.keypad_index(c) {
+                    pressed[k as usize] = match key.kind {
+                        KeyEventKind::Release => None,
+                        KeyEventKind::Press | KeyEventKind::Repeat => Some(start),
+                    };
+                }
+            }
+        }
+        for (k, at) in pressed.iter_mut().enumerate() {
+            if !raw.enhanced && at.is_some_and(|t| start - t > KEY_HOLD) {
+                *at = None;
+            }
+            vm.set_key(k as u8, at.is_some());
+        }
+
+        if let Err(f) = vm.run_frame() {
+            fault = Some(f);
+            break;
+        }
+
+        if vm.sound_active() && !beeping {
+            queue!(out, crossterm::style::Print('\x07'))?;
+        }
+        beeping = vm.sound_active();
+
+        // only rows that changed are dirty, but a frame is cheap enough to redo whole
+        redraw |= vm.take_dirty_rows() != 0;
+        if redraw {
+            let frame = render(&vm, glyphs);
+            queue!(out, cursor::MoveTo(0, 0), crossterm::style::Print(&frame))?;
+            redraw = false;
+        }
+        out.flush()?;
+
+        if let Some(rest) = FRAME.checked_sub(start.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+
+    drop(raw);
+    match fault {
+        Some(f) => Err(std::io::Error::other(format!(
+            "stopped at {:#06X}: {f}",
+            vm.program_counter()
+        ))),
+        None => Ok(()),
+    }
+}
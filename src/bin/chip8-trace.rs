@@ -0,0 +1,77 @@
+// Records our execution trace of a ROM, or checks it against a trace from another
+// emulator (see `chip8::trace` for the CSV and JSON lines formats):
+//
+//     chip8-trace --record ours.csv --steps 100000 roms/brix.ch8
+//     chip8-trace --compare reference.jsonl roms/brix.ch8
+//
+// A comparison prints the first instruction where the two disagree, with the
+// reference and our state side by side, and exits with status 1. Labels from
+// `--symbols FILE`, or the ROM's `.sym` sidecar, are added to both (see
+// `chip8::symbols`).
+use chip8::config::{Config, ConfigLayer};
+use chip8::patch;
+use chip8::symbols::Symbols;
+use chip8::trace;
+use chip8::VM;
+use std::io::BufWriter;
+
+fn main() -> std::io::Result<()> {
+    let mut rom = None;
+    let mut steps = 10_000;
+    let mut record = None;
+    let mut compare = None;
+    let mut symbols = None;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => steps = args.next().and_then(|n| n.parse().ok()).unwrap_or(steps),
+            "--record" => record = args.next(),
+            "--compare" => compare = args.next(),
+            "--symbols" => symbols = args.next(),
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--memory" => cli.memory = args.next(),
+            _ => rom = Some(arg),
+        }
+    }
+    let (Some(path), true) = (rom, record.is_some() || compare.is_some()) else {
+        println!("Usage: chip8-trace (--record FILE [--steps N] | --compare TRACE) [--symbols FILE] [--ipf N | --vip] [--platform P] [--memory M] ROM");
+        return Ok(());
+    };
+
+    let rom = patch::load(path.as_ref(), &[])?;
+    let config = Config::resolve(&rom, path.as_ref(), &cli)?;
+    let mut vm = VM::from_config(&config);
+    vm.load_rom(&rom)?;
+    let symbols = Symbols::for_rom(path.as_ref(), symbols.as_deref())?;
+
+    if let Some(file) = record {
+        let (steps, fault) = match trace::record(&mut vm, steps) {
+            Ok(steps) => (steps, None),
+            Err((steps, fault)) => (steps, Some(fault)),
+        };
+        let out = BufWriter::new(std::fs::File::create(&file)?);
+        trace::write_csv(out, &steps, symbols.as_ref())?;
+        println!("{} steps written to {file}", steps.len());
+        if let Some(fault) = fault {
+            println!("Stopped at {:#06X}: {fault}", vm.program_counter());
+        }
+        // a comparison starts over from a freshly loaded ROM
+        vm = VM::from_config(&config);
+        vm.load_rom(&rom)?;
+    }
+
+    if let Some(file) = compare {
+        let reference = trace::read(&file)?;
+        match trace::compare(&mut vm, &reference) {
+            Ok(matched) => println!("All {matched} steps match {file}"),
+            Err(divergence) => {
+                println!("{}", divergence.report(symbols.as_ref()));
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
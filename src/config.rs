@@ -0,0 +1,334 @@
+// Emulator settings, resolved in layers from lowest to highest priority:
+// built-in defaults, the ROM database, `<config dir>/chip8/roms/<sha1>.toml`,
+// a sidecar `<rom>.toml` next to the ROM, and finally command line flags.
+use crate::database::{Rom, RomInfo};
+use crate::{
+    Filter, MemoryMode, Palette, Platform, QuirkOverrides, Quirks, Scaling, Timing, KEYMAP,
+};
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub timing: Timing,
+    pub quirks: Quirks,
+    pub palette: Palette,
+    pub keymap: [char; 16],
+    pub scaling: Scaling,
+    pub filter: Filter,
+    pub memory: MemoryMode,
+    pub files: Vec<PathBuf>, // config files that were merged in
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timing: Timing::Fixed(crate::INSTRUCTIONS_PER_FRAME),
+            quirks: Quirks::default(),
+            palette: Palette::default(),
+            keymap: KEYMAP,
+            scaling: Scaling::Integer,
+            filter: Filter::None,
+            memory: MemoryMode::Wrap4K,
+            files: vec![],
+        }
+    }
+}
+
+// One layer of settings, as read from a TOML file or built from command line flags.
+// Anything left unset falls through to the layer below.
+//
+//     cycles_per_frame = 15      # or timing = "vip"
+//     platform = "schip"         # quirk preset, tweaked further by [quirks]
+//     palette = ["#000000", "#ffffff"]
+//     keymap = "x123qweasdzc4rfv" # host keys for CHIP-8 keys 0 to F
+//     scaling = "fractional"
+//     filter = "phosphor"        # "none", "blend" or "phosphor"
+//     phosphor_decay = 0.6
+//     memory = "strict"          # "wrap4k", "wrap64k" or "strict" (fault past 4 KiB)
+//
+//     [quirks]                   # names as in the ROM database
+//     shift = false
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub cycles_per_frame: Option<u32>,
+    pub timing: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: QuirkOverrides,
+    pub palette: Option<[String; 2]>,
+    pub keymap: Option<String>,
+    pub scaling: Option<String>,
+    pub filter: Option<String>,
+    pub phosphor_decay: Option<f32>,
+    pub memory: Option<String>,
+}
+
+impl ConfigLayer {
+    pub fn parse(toml: &str) -> Result<Self, String> {
+        toml::from_str(toml).map_err(|e| e.to_string())
+    }
+
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(n) = self.cycles_per_frame {
+            config.timing = Timing::Fixed(n);
+        }
+        match self.timing.as_deref() {
+            Some("vip") => config.timing = Timing::Vip,
+            // a rate from this layer was applied above, otherwise a lower layer's stays
+            Some("fixed") if config.timing == Timing::Vip => {
+                config.timing = Timing::Fixed(crate::INSTRUCTIONS_PER_FRAME);
+            }
+            None | Some("fixed") => {}
+            Some(t) => return Err(format!("unknown timing {t}, expected fixed or vip")),
+        }
+
+        if let Some(platform) = &self.platform {
+            config.quirks = platform.parse::<Platform>()?.quirks();
+        }
+        self.quirks.apply(&mut config.quirks);
+
+        if let Some([background, foreground]) = &self.palette {
+            let parse = |c: &str| {
+                crate::database::parse_color(c)
+                    .ok_or(format!("invalid colour {c}, expected #rrggbb"))
+            };
+            config.palette = Palette {
+                background: parse(background)?,
+                foreground: parse(foreground)?,
+            };
+        }
+
+        if let Some(keymap) = &self.keymap {
+            let keys: Vec<char> = keymap.chars().collect();
+            config.keymap = keys
+                .try_into()
+                .map_err(|_| format!("keymap {keymap} must have exactly 16 keys"))?;
+        }
+
+        match self.scaling.as_deref() {
+            None => {}
+            Some("integer") => config.scaling = Scaling::Integer,
+            Some("fractional") => config.scaling = Scaling::Fractional,
+            Some(s) => {
+                return Err(format!(
+                    "unknown scaling {s}, expected integer or fractional"
+                ))
+            }
+        }
+
+        let decay = self.phosphor_decay.unwrap_or(match config.filter {
+            Filter::Phosphor { decay } => decay,
+            _ => 0.6,
+        });
+        match self.filter.as_deref() {
+            None if self.phosphor_decay.is_some() => config.filter = Filter::Phosphor { decay },
+            None => {}
+            Some("none") => config.filter = Filter::None,
+            Some("blend") => config.filter = Filter::Blend,
+            Some("phosphor") => config.filter = Filter::Phosphor { decay },
+            Some(f) => {
+                return Err(format!(
+                    "unknown filter {f}, expected none, blend or phosphor"
+                ))
+            }
+        }
+
+        if let Some(memory) = &self.memory {
+            config.memory = memory.parse()?;
+        }
+        Ok(())
+    }
+
+    fn read(path: &Path) -> io::Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::parse(&text).map(Some).map_err(|e| invalid(path, &e))
+    }
+}
+
+impl From<&RomInfo> for ConfigLayer {
+    fn from(info: &RomInfo) -> Self {
+        let mut quirks = QuirkOverrides::default();
+        let q = info.quirks;
+        quirks.shift = Some(q.shift);
+        quirks.memory_increment_by_x = Some(q.memory_increment_by_x);
+        quirks.memory_leave_i_unchanged = Some(q.memory_leave_i_unchanged);
+        quirks.wrap = Some(q.wrap);
+        quirks.jump = Some(q.jump);
+        quirks.vblank = Some(q.vblank);
+        quirks.logic = Some(q.logic);
+
+        let hex = |c| {
+            let [r, g, b] = crate::vm::to_rgb(c);
+            format!("#{r:02x}{g:02x}{b:02x}")
+        };
+        Self {
+            cycles_per_frame: info.tickrate,
+            quirks,
+            palette: info.palette.map(|p| [hex(p.background), hex(p.foreground)]),
+            ..Self::default()
+        }
+    }
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {message}", path.display()),
+    )
+}
+
+// per-user configuration directory, e.g. ~/.config/chip8
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("chip8"))
+}
+
+// `breakout.ch8` -> `breakout.ch8.toml`, for files that travel with a ROM
+#[must_use]
+pub fn sidecar(rom_path: &Path, extension: &str) -> PathBuf {
+    let mut name = rom_path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+impl Config {
+    pub fn resolve(rom: &Rom, rom_path: &Path, cli: &ConfigLayer) -> io::Result<Self> {
+        Self::resolve_with(
+            Config::default(),
+            config_dir().as_deref(),
+            rom,
+            rom_path,
+            cli,
+        )
+    }
+
+    // The same, with the layers applied over `config` rather than the defaults and
+    // per-ROM user files read from `user_dir` rather than `config_dir()`.
+    pub fn resolve_with(
+        mut config: Config,
+        user_dir: Option<&Path>,
+        rom: &Rom,
+        rom_path: &Path,
+        cli: &ConfigLayer,
+    ) -> io::Result<Self> {
+        if let Some(info) = &rom.info {
+            ConfigLayer::from(info)
+                .apply(&mut config)
+                .map_err(|e| invalid(Path::new("ROM database"), &e))?;
+        }
+
+        let user = user_dir.map(|d| d.join("roms").join(format!("{}.toml", rom.sha1)));
+        for path in user.into_iter().chain([sidecar(rom_path, "toml")]) {
+            if let Some(layer) = ConfigLayer::read(&path)? {
+                layer.apply(&mut config).map_err(|e| invalid(&path, &e))?;
+                config.files.push(path);
+            }
+        }
+
+        cli.apply(&mut config)
+            .map_err(|e| invalid(Path::new("command line"), &e))?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, ConfigLayer};
+    use crate::database::Rom;
+    use crate::{Filter, MemoryMode, Platform, Scaling, Timing};
+
+    #[test]
+    fn test_parse_layer() {
+        let layer = ConfigLayer::parse(
+            r##"
+            cycles_per_frame = 20
+            platform = "schip"
+            palette = ["#000000", "#ffffff"]
+            keymap = "0123456789abcdef"
+            filter = "blend"
+            memory = "wrap64k"
+
+            [quirks]
+            jump = false
+            "##,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        layer.apply(&mut config).unwrap();
+        assert_eq!(config.timing, Timing::Fixed(20));
+        assert!(config.quirks.shift);
+        assert!(!config.quirks.jump);
+        assert_eq!(config.keymap[0xA], 'a');
+        assert_eq!(config.filter, Filter::Blend);
+        assert_eq!(config.memory, MemoryMode::Wrap64K);
+        assert_eq!(config.palette.foreground, macroquad::color::WHITE);
+    }
+
+    #[test]
+    fn test_invalid_layer() {
+        assert!(ConfigLayer::parse("unknown = 1").is_err());
+        let layer = ConfigLayer::parse("keymap = \"abc\"").unwrap();
+        assert!(layer.apply(&mut Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_layer_priority() {
+        let dir = std::env::temp_dir().join(format!("chip8-config-{}", std::process::id()));
+        let user_dir = dir.join("user");
+        std::fs::create_dir_all(user_dir.join("roms")).unwrap();
+        let rom_path = dir.join("brix.ch8");
+        std::fs::copy("roms/brix.ch8", &rom_path).unwrap();
+        let rom = Rom::new(std::fs::read(&rom_path).unwrap());
+        let user = user_dir.join("roms").join(format!("{}.toml", rom.sha1));
+        std::fs::write(&user, "cycles_per_frame = 20\nfilter = \"blend\"").unwrap();
+        std::fs::write(
+            dir.join("brix.ch8.toml"),
+            "timing = \"fixed\"\nscaling = \"fractional\"",
+        )
+        .unwrap();
+
+        let resolve = |cli: &ConfigLayer| {
+            Config::resolve_with(Config::default(), Some(&user_dir), &rom, &rom_path, cli)
+        };
+        let config = resolve(&ConfigLayer::default()).unwrap();
+        let cli = ConfigLayer {
+            cycles_per_frame: Some(40),
+            ..ConfigLayer::default()
+        };
+        let faster = resolve(&cli).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // database says original CHIP-8, the user file sets the speed and filter, the
+        // sidecar asks for fixed timing without a rate so the user's rate stays
+        assert_eq!(config.quirks, Platform::Chip8.quirks());
+        assert_eq!(config.filter, Filter::Blend);
+        assert_eq!(config.scaling, Scaling::Fractional);
+        assert_eq!(config.timing, Timing::Fixed(20));
+        assert_eq!(config.files, vec![user, dir.join("brix.ch8.toml")]);
+        // the command line wins on speed
+        assert_eq!(faster.timing, Timing::Fixed(40));
+    }
+
+    #[test]
+    fn test_fixed_timing_after_vip() {
+        let mut config = Config {
+            timing: Timing::Vip,
+            ..Config::default()
+        };
+        let fixed = ConfigLayer::parse("timing = \"fixed\"").unwrap();
+        fixed.apply(&mut config).unwrap();
+        assert_eq!(config.timing, Timing::Fixed(crate::INSTRUCTIONS_PER_FRAME));
+    }
+}
@@ -0,0 +1,659 @@
+// Debug Adapter Protocol server, for debugging a CHIP-8 program from an editor.
+// Messages are JSON with a Content-Length header, over stdin/stdout or a socket
+// (see `chip8-dap`). A launch request looks like:
+//
+//     { "program": "game.ch8", "source": "game.8o", "lineMap": "game.ch8.map",
+//       "symbols": "game.ch8.sym", "stopOnEntry": true }
+//
+// `lineMap` (see `chip8::linemap`) defaults to the `.map` sidecar of the program and
+// is what turns source line breakpoints into addresses. Without one, breakpoints
+// can still be set on instruction addresses. Labels from `symbols` (see
+// `chip8::symbols`, default the `.sym` sidecar) name stack frames and can be used
+// as function breakpoints. Registers and the stack are shown as variables, and I,
+// PC and return addresses open the memory view via readMemory.
+//
+// There is a single thread. While running, requests are read on a second thread
+// and handled between 60Hz frames.
+use crate::config::{sidecar, Config, ConfigLayer};
+use crate::linemap::LineMap;
+use crate::patch;
+use crate::symbols::{describe, Symbols};
+use crate::{Cheats, OpCode, VM};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const THREAD: u64 = 1;
+const REGISTERS: u64 = 1; // variablesReference of each scope
+const STACK: u64 = 2;
+
+// reads one message, None at end of input
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && len.is_some() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; len.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// "0x0200", as used for memory and instruction references
+fn parse_address(reference: &str) -> Option<i64> {
+    let hex = reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix("0X"))
+        .unwrap_or(reference);
+    i64::from_str_radix(hex, 16).ok()
+}
+
+// where a run started by continue or a step stops, other than at a breakpoint
+#[derive(Debug, Clone, Copy)]
+enum Until {
+    Breakpoint,
+    // an instruction at most `depth` calls deep, on a different source line when
+    // `line` is set
+    Line { line: Option<u32>, depth: usize },
+    Return { depth: usize }, // out of the subroutine `depth` calls deep
+}
+
+impl Until {
+    fn done(self, vm: &VM, map: Option<&LineMap>) -> bool {
+        let depth = vm.stack().len();
+        match self {
+            Until::Breakpoint => false,
+            Until::Line { line, depth: max } => {
+                depth <= max
+                    && (line.is_none() || map.and_then(|m| m.line(vm.program_counter())) != line)
+            }
+            Until::Return { depth: from } => depth < from,
+        }
+    }
+}
+
+pub struct DapServer<W: Write> {
+    output: W,
+    seq: u64,
+    cli: ConfigLayer,
+    vm: VM,
+    source: Option<String>,
+    line_map: Option<LineMap>,
+    symbols: Option<Symbols>,
+    line_breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    function_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+    running: Option<Until>,
+}
+
+impl<W: Write> DapServer<W> {
+    // `cli` overrides the ROM's configuration like the frontends' flags do
+    pub fn new(output: W, cli: ConfigLayer) -> Self {
+        Self {
+            output,
+            seq: 0,
+            cli,
+            vm: VM::new(),
+            source: None,
+            line_map: None,
+            symbols: None,
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            function_breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            running: None,
+        }
+    }
+
+    // Serves one client until it disconnects or the input ends.
+    pub fn run<R: Read + Send + 'static>(&mut self, input: R) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut input = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut next_frame = Instant::now();
+        loop {
+            let message = if self.running.is_some() {
+                match rx.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match rx.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return Ok(()),
+                }
+            };
+            if let Some(message) = message {
+                if !self.handle(&message)? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            self.run_frame()?;
+            let now = Instant::now();
+            next_frame = (next_frame + FRAME).max(now);
+            std::thread::sleep(next_frame - now);
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        message["body"] = body;
+        self.send(message)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        self.running = None;
+        let mut body = json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["description"] = json!(format!("Stopped: {text}"));
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    // returns false once the client is done with us
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsSteppingGranularity": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(args)),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ]})),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64())),
+            "readMemory" => self.read_memory(args),
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" | "pause" => {
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "setExceptionBreakpoints" | "disconnect" | "terminate" => Ok(json!({})),
+            _ => Err(format!("unsupported request {command:?}")),
+        };
+        let launched = result.is_ok();
+        self.respond(request, result)?;
+
+        // anything that changes the run state is reported after the response
+        let by_line = self.line_map.is_some() && args["granularity"] != "instruction";
+        let line = if by_line {
+            self.line_map
+                .as_ref()
+                .and_then(|m| m.line(self.vm.program_counter()))
+        } else {
+            None
+        };
+        let depth = self.vm.stack().len();
+        match command {
+            "launch" if launched => self.event("initialized", json!({}))?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None)?,
+            "configurationDone" => self.running = Some(Until::Breakpoint),
+            "continue" => self.resume(Until::Breakpoint)?,
+            "next" => self.resume(Until::Line { line, depth })?,
+            "stepIn" => self.resume(Until::Line {
+                line,
+                depth: usize::MAX,
+            })?,
+            "stepOut" => self.resume(Until::Return { depth })?,
+            "pause" if self.running.is_some() => self.stopped("pause", None)?,
+            "terminate" => {
+                self.event("terminated", json!({}))?;
+                return Ok(false);
+            }
+            "disconnect" => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs a \"program\"")?;
+        let path = Path::new(program);
+        let rom = patch::load(path, &[]).map_err(|e| format!("{program}: {e}"))?;
+        let config = Config::resolve(&rom, path, &self.cli).map_err(|e| e.to_string())?;
+        let line_map = match args["lineMap"].as_str() {
+            Some(map) => Some(LineMap::read(map).map_err(|e| e.to_string())?),
+            None => LineMap::read(sidecar(path, "map")).ok(),
+        };
+        let symbols =
+            Symbols::for_rom(path, args["symbols"].as_str()).map_err(|e| e.to_string())?;
+
+        self.vm = VM::from_config(&config);
+        self.vm
+            .load_rom(&rom)
+            .map_err(|e| format!("{program}: {e}"))?;
+        self.vm
+            .set_cheats(Cheats::for_rom(&rom, path).map_err(|e| e.to_string())?);
+        self.line_map = line_map;
+        self.symbols = symbols;
+        self.source = args["source"].as_str().map(str::to_string);
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    // Each requested line is moved to the first line at or after it that has code,
+    // and breaks on every address assembled from that line.
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        self.line_breakpoints.clear();
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = vec![];
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as u32;
+            let Some(map) = self.line_map.as_ref() else {
+                breakpoints
+                    .push(json!({ "verified": false, "message": "no line map for this program" }));
+                continue;
+            };
+            match map.nearest_line(line) {
+                Some(line) => {
+                    self.line_breakpoints.extend(map.addresses(line));
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints
+                    .push(json!({ "verified": false, "message": "no code at or after this line" })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = vec![];
+        for breakpoint in requested {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(parse_address)
+                .map(|a| a + breakpoint["offset"].as_i64().unwrap_or(0))
+                .and_then(|a| u16::try_from(a).ok());
+            match address {
+                Some(address) => {
+                    self.instruction_breakpoints.insert(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("{address:#06X}"),
+                    }));
+                }
+                None => breakpoints.push(json!({ "verified": false })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    // Executes the instruction at PC, so a breakpoint there doesn't stop us again,
+    // then keeps running frames until `until` or a breakpoint.
+    fn resume(&mut self, until: Until) -> io::Result<()> {
+        if let Err(fault) = self.vm.step() {
+            return self.stopped("exception", Some(fault.to_string()));
+        }
+        self.running = Some(until);
+        Ok(())
+    }
+
+    // by label, `label+offset` or address
+    fn set_function_breakpoints(&mut self, args: &Value) -> Value {
+        self.function_breakpoints.clear();
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = vec![];
+        for breakpoint in requested {
+            let name = breakpoint["name"].as_str().unwrap_or_default();
+            let address = match &self.symbols {
+                Some(symbols) => symbols.resolve(name),
+                None => Symbols::default().resolve(name),
+            };
+            match address {
+                Some(address) => {
+                    self.function_breakpoints.insert(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("{address:#06X}"),
+                    }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "message": "unknown label" })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn run_frame(&mut self) -> io::Result<()> {
+        let Some(until) = self.running else {
+            return Ok(());
+        };
+        let mut reason = "step";
+        let result = {
+            let map = self.line_map.as_ref();
+            let breakpoints = [
+                &self.line_breakpoints,
+                &self.instruction_breakpoints,
+                &self.function_breakpoints,
+            ];
+            self.vm.run_frame_until(|vm| {
+                let pc = vm.program_counter();
+                if breakpoints.iter().any(|b| b.contains(&pc)) {
+                    reason = "breakpoint";
+                    return true;
+                }
+                until.done(vm, map)
+            })
+        };
+        match result {
+            Ok(true) => self.stopped(reason, None),
+            Ok(false) => Ok(()),
+            Err(fault) => self.stopped("exception", Some(fault.to_string())),
+        }
+    }
+
+    fn frame(&self, id: usize, address: u16) -> Value {
+        let op = match self.vm.read_memory(address, 2) {
+            &[hi, lo] => OpCode::from_bytes((hi, lo)).to_string(),
+            _ => String::new(),
+        };
+        let mut frame = json!({
+            "id": id,
+            "name": format!("{}  {op}", describe(self.symbols.as_ref(), address)),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{address:#06X}"),
+        });
+        let line = self.line_map.as_ref().and_then(|m| m.line(address));
+        if let (Some(source), Some(line)) = (&self.source, line) {
+            let name = Path::new(source)
+                .file_name()
+                .map_or(source.clone(), |n| n.to_string_lossy().into_owned());
+            frame["source"] = json!({ "name": name, "path": source });
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    // the current instruction, then the CALL of every subroutine on the stack
+    fn stack_trace(&self) -> Value {
+        let mut frames = vec![self.frame(0, self.vm.program_counter())];
+        for (id, &ret) in self.vm.stack().iter().rev().enumerate() {
+            frames.push(self.frame(id + 1, ret.wrapping_sub(2)));
+        }
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Value {
+        let vm = &self.vm;
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let address = |name: String, address: u16| {
+            json!({
+                "name": name,
+                "value": format!("{address:#06X}"),
+                "variablesReference": 0,
+                "memoryReference": format!("{address:#06X}"),
+            })
+        };
+        let mut variables = vec![];
+        match reference {
+            Some(REGISTERS) => {
+                for (v, value) in vm.registers().iter().enumerate() {
+                    variables.push(variable(format!("V{v:X}"), format!("{value:#04X}")));
+                }
+                variables.push(address("I".to_string(), vm.index()));
+                variables.push(address("PC".to_string(), vm.program_counter()));
+                variables.push(variable("SP".to_string(), vm.stack().len().to_string()));
+                variables.push(variable("DT".to_string(), vm.delay_timer().to_string()));
+                variables.push(variable("ST".to_string(), vm.sound_timer().to_string()));
+            }
+            Some(STACK) => {
+                for (n, &ret) in vm.stack().iter().enumerate().rev() {
+                    variables.push(address(format!("[{n}]"), ret));
+                }
+            }
+            _ => {}
+        }
+        json!({ "variables": variables })
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or_default();
+        let start = parse_address(reference).ok_or("invalid memory reference")?
+            + args["offset"].as_i64().unwrap_or(0);
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let bytes = match u16::try_from(start) {
+            Ok(address) => self.vm.read_memory(address, count),
+            Err(_) => &[],
+        };
+        Ok(json!({
+            "address": format!("{start:#06X}"),
+            "data": base64(bytes),
+            "unreadableBytes": count - bytes.len(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64, read_message, write_message, DapServer};
+    use crate::config::ConfigLayer;
+    use serde_json::{json, Value};
+    use std::io::{BufReader, PipeReader, PipeWriter};
+
+    // a scripted editor
+    struct Client {
+        requests: PipeWriter,
+        replies: BufReader<PipeReader>,
+        seq: u64,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (requests_in, requests) = std::io::pipe().unwrap();
+            let (replies, replies_out) = std::io::pipe().unwrap();
+            std::thread::spawn(move || {
+                DapServer::new(replies_out, ConfigLayer::default())
+                    .run(requests_in)
+                    .unwrap();
+            });
+            Self {
+                requests,
+                replies: BufReader::new(replies),
+                seq: 0,
+            }
+        }
+
+        fn send(&mut self, command: &str, arguments: Value) -> u64 {
+            self.seq += 1;
+            let mut request = json!({ "seq": self.seq, "type": "request", "command": command });
+            request["arguments"] = arguments;
+            write_message(&mut self.requests, &request).unwrap();
+            self.seq
+        }
+
+        fn next(&mut self) -> Value {
+            read_message(&mut self.replies).unwrap().unwrap()
+        }
+
+        // body of the response, skipping events
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            let seq = self.send(command, arguments);
+            loop {
+                let message = self.next();
+                if message["request_seq"] == seq {
+                    assert_eq!(message["success"], true, "{message}");
+                    return message["body"].clone();
+                }
+            }
+        }
+
+        fn event(&mut self, event: &str) -> Value {
+            loop {
+                let message = self.next();
+                if message["event"] == event {
+                    return message["body"].clone();
+                }
+            }
+        }
+
+        fn top_frame(&mut self) -> Value {
+            self.request("stackTrace", json!({ "threadId": 1 }))["stackFrames"][0].clone()
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(&[0x70, 0x01, 0x71, 0x01]), "cAFxAQ==");
+        assert_eq!(base64(b"Man"), "TWFu");
+    }
+
+    #[test]
+    fn test_debug_session() {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("demo.ch8");
+        // ADD V0, 1; ADD V1, 1; JMP 0x200
+        std::fs::write(&rom, [0x70, 0x01, 0x71, 0x01, 0x12, 0x00]).unwrap();
+        std::fs::write(dir.join("demo.ch8.map"), "0x200 3\n0x202 4\n0x204 6\n").unwrap();
+        std::fs::write(dir.join("demo.ch8.sym"), "0x200 main\n0x204 again\n").unwrap();
+
+        let mut dap = Client::start();
+        let caps = dap.request("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(caps["supportsReadMemoryRequest"], true);
+        dap.request(
+            "launch",
+            json!({ "program": rom, "source": "demo.8o", "stopOnEntry": false }),
+        );
+        dap.event("initialized");
+        let set = dap.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "demo.8o" }, "breakpoints": [{ "line": 4 }, { "line": 9 }] }),
+        );
+        assert_eq!(
+            set["breakpoints"][0],
+            json!({ "verified": true, "line": 4 })
+        );
+        assert_eq!(set["breakpoints"][1]["verified"], false);
+
+        dap.request("configurationDone", json!({}));
+        assert_eq!(dap.event("stopped")["reason"], "breakpoint");
+        let frame = dap.top_frame();
+        assert_eq!(
+            (&frame["line"], &frame["name"]),
+            (&json!(4), &json!("main+0x02  ADD - 0x7101"))
+        );
+        let registers = dap.request("variables", json!({ "variablesReference": 1 }));
+        assert_eq!(
+            registers["variables"][0],
+            json!({ "name": "V0", "value": "0x01", "variablesReference": 0 })
+        );
+
+        dap.request("next", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "step");
+        assert_eq!(dap.top_frame()["line"], 6);
+        dap.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "breakpoint");
+
+        dap.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "demo.8o" }, "breakpoints": [] }),
+        );
+        dap.request("continue", json!({ "threadId": 1 }));
+        dap.request("pause", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "pause");
+
+        let set = dap.request(
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "again" }] }),
+        );
+        assert_eq!(set["breakpoints"][0]["instructionReference"], "0x0204");
+        dap.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(dap.event("stopped")["reason"], "breakpoint");
+        assert_eq!(dap.top_frame()["name"], "again  JMP - 0x1200");
+
+        let memory = dap.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0200", "count": 4 }),
+        );
+        assert_eq!(memory["data"], "cAFxAQ==");
+        dap.request("disconnect", json!({}));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+// Lookup of ROMs by SHA-1 in the bundled copy of the community chip-8-database
+// (https://github.com/chip-8/chip-8-database), MIT licensed. See db/README.md for
+// the version vendored and db/update.sh to fetch it again.
+use crate::{Palette, Platform, QuirkOverrides, Quirks};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+static PROGRAMS: &str = include_str!("../db/programs.json");
+static HASHES: &str = include_str!("../db/sha1-hashes.json");
+static PLATFORMS: &str = include_str!("../db/platforms.json");
+
+#[derive(Debug, Deserialize)]
+struct Program {
+    title: String,
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlatformEntry {
+    id: String,
+    quirks: QuirkOverrides,
+}
+
+#[derive(Debug, Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+struct Database {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: HashMap<String, QuirkOverrides>,
+}
+
+fn database() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+    DATABASE.get_or_init(|| Database {
+        programs: serde_json::from_str(PROGRAMS).expect("bundled programs.json is invalid"),
+        hashes: serde_json::from_str(HASHES).expect("bundled sha1-hashes.json is invalid"),
+        platforms: serde_json::from_str::<Vec<PlatformEntry>>(PLATFORMS)
+            .expect("bundled platforms.json is invalid")
+            .into_iter()
+            .map(|p| (p.id, p.quirks))
+            .collect(),
+    })
+}
+
+// settings the database recommends for a ROM
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub tickrate: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keys: BTreeMap<String, u8>, // hints such as "left" -> 0x4
+}
+
+#[derive(Debug, Clone)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub sha1: String,
+    pub info: Option<RomInfo>,
+}
+
+impl Rom {
+    #[must_use]
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let sha1 = sha1_hex(&bytes);
+        let info = lookup(&sha1);
+        Self { bytes, sha1, info }
+    }
+}
+
+#[must_use]
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    Sha1::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut out, b| {
+            let _ = write!(out, "{b:02x}");
+            out
+        })
+}
+
+#[must_use]
+pub fn lookup(sha1: &str) -> Option<RomInfo> {
+    let db = database();
+    let program = db
+        .programs
+        .get(*db.hashes.get(&sha1.to_ascii_lowercase())?)?;
+    let rom = program.roms.get(&sha1.to_ascii_lowercase())?;
+
+    // first platform listed that we can emulate
+    let (id, platform, mut quirks) = rom.platforms.iter().find_map(|id| {
+        let platform = platform(id)?;
+        let mut quirks = platform.quirks();
+        db.platforms.get(id)?.apply(&mut quirks);
+        Some((id, platform, quirks))
+    })?;
+    if let Some(overrides) = rom.quirky_platforms.get(id) {
+        overrides.apply(&mut quirks);
+    }
+
+    let palette = rom.colors.as_ref().and_then(|c| {
+        Some(Palette {
+            background: parse_color(c.pixels.first()?)?,
+            foreground: parse_color(c.pixels.get(1)?)?,
+        })
+    });
+
+    Some(RomInfo {
+        title: program.title.clone(),
+        platform,
+        quirks,
+        tickrate: rom.tickrate,
+        palette,
+        keys: rom.keys.clone(),
+    })
+}
+
+// platform ids from platforms.json that we can emulate, their quirks come from there too
+fn platform(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::Schip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+// "#rrggbb"
+pub(crate) fn parse_color(color: &str) -> Option<macroquad::color::Color> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Some(macroquad::color::Color::from_rgba(r, g, b, 255))
+}
+
+impl std::fmt::Display for Rom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "SHA-1:    {}", self.sha1)?;
+        let Some(info) = &self.info else {
+            return writeln!(f, "Not in the ROM database, using defaults");
+        };
+        writeln!(f, "Title:    {}", info.title)?;
+        writeln!(f, "Platform: {}", info.platform)?;
+        writeln!(f, "Quirks:   {:?}", info.quirks)?;
+        if let Some(t) = info.tickrate {
+            writeln!(f, "Tickrate: {t}")?;
+        }
+        if let Some(p) = info.palette {
+            let [br, bg, bb] = crate::vm::to_rgb(p.background);
+            let [fr, fg, fb] = crate::vm::to_rgb(p.foreground);
+            writeln!(
+                f,
+                "Palette:  #{br:02x}{bg:02x}{bb:02x} #{fr:02x}{fg:02x}{fb:02x}"
+            )?;
+        }
+        if !info.keys.is_empty() {
+            let keys: Vec<String> = info
+                .keys
+                .iter()
+                .map(|(k, v)| format!("{k}={v:X}"))
+                .collect();
+            writeln!(f, "Keys:     {}", keys.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lookup, Rom};
+    use crate::Platform;
+
+    #[test]
+    fn test_bundled_roms_are_found() {
+        let rom = Rom::new(std::fs::read("roms/brix.ch8").unwrap());
+        assert_eq!(rom.sha1, "f13766c14aeb02ad8d4d103cb5eadd282d20cddc");
+        let info = rom.info.unwrap();
+        assert_eq!(info.title, "Brix");
+        assert_eq!(info.platform, Platform::Chip8);
+        assert_eq!(info.quirks, Platform::Chip8.quirks());
+
+        let info = Rom::new(std::fs::read("roms/breakout.ch8").unwrap()).info;
+        assert_eq!(info.unwrap().keys.get("left"), Some(&4));
+    }
+
+    #[test]
+    fn test_unknown_rom() {
+        assert!(Rom::new(vec![0x12, 0x00]).info.is_none());
+        assert!(lookup("not a hash").is_none());
+    }
+
+    #[test]
+    fn test_modern_platform() {
+        // Glitch Ghost
+        let info = lookup("2CDCB3C29A5F013A991DB5909CA8E18E27B3C42B").unwrap();
+        assert!(!info.quirks.vblank);
+        assert!(!info.quirks.shift);
+    }
+}
@@ -0,0 +1,784 @@
+// GDB remote serial protocol stub, so a VM can be driven from gdb or anything
+// else that speaks RSP over TCP:
+//
+//     chip8-gdb --port 1234 roms/brix.ch8
+//     (gdb) target remote localhost:1234
+//
+// gdb has no CHIP-8 architecture, so the registers are described by target.xml:
+// V0-VF, I, PC, SP, DT and ST, numbered 0 to 20. Values are big-endian like CHIP-8
+// memory, I and PC are 2 bytes and the rest 1. SP is the stack depth, 0 when empty.
+//
+// Breakpoints are software breakpoints kept by the stub (Z0, with Z1 treated the
+// same) and checked before every instruction, so memory is never patched. Continue
+// runs 60Hz frames in real time until a breakpoint, a fault or Ctrl-C.
+//
+// gdb knows nothing of CHIP-8 labels, so with symbols loaded they are reached
+// through monitor commands: `monitor break main_loop`, `monitor where`. Cheats and
+// RAM search are monitor commands too: `monitor search`, `monitor search decreased`,
+// `monitor cheat 0x3F5 = 05 lives`, `monitor cheat off 0`.
+use crate::symbols::{describe, Symbols};
+use crate::{Cheats, Fault, RamSearch, SearchFilter, VM};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const REGISTERS: usize = 21;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DT: usize = 19;
+const ST: usize = 20;
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+fn register_size(n: usize) -> usize {
+    if n == I || n == PC {
+        2
+    } else {
+        1
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for v in 0..16 {
+        let _ = write!(xml, "<reg name=\"v{v:x}\" bitsize=\"8\" type=\"uint8\"/>");
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\
+         </feature></target>",
+    );
+    xml
+}
+
+fn fault_signal(fault: Fault) -> u8 {
+    match fault {
+        Fault::UnknownOpcode(_) => SIGILL,
+        Fault::Address(_) | Fault::StackOverflow | Fault::StackUnderflow => SIGSEGV,
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+// `addr,len` as used by m, M and Z packets
+fn parse_range(s: &str) -> Option<(u16, usize)> {
+    let (address, len) = s.split_once(',')?;
+    Some((u16::try_from(parse_hex(address)?).ok()?, parse_hex(len)?))
+}
+
+fn query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+".to_string();
+    }
+    if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        let xml = target_xml();
+        let Some((offset, len)) = range
+            .split_once(',')
+            .and_then(|(o, l)| Some((parse_hex(o)?, parse_hex(l)?)))
+        else {
+            return "E01".to_string();
+        };
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(len).min(xml.len());
+        let more = if end < xml.len() { 'm' } else { 'l' };
+        return format!("{more}{}", &xml[start..end]);
+    }
+    match query {
+        "Attached" => "1",
+        "C" => "QC1",
+        "fThreadInfo" => "m1",
+        "sThreadInfo" => "l",
+        "Symbol::" => "OK",
+        _ => "",
+    }
+    .to_string()
+}
+
+enum Input {
+    Packet(String),
+    Interrupt, // Ctrl-C, a bare 0x03 byte outside any packet
+}
+
+struct Connection {
+    stream: TcpStream,
+    received: Vec<u8>,
+    no_ack: bool,
+}
+
+impl Connection {
+    // takes the next complete packet or interrupt off the receive buffer, acking
+    // packets unless no-ack mode is on; acks from gdb are skipped
+    fn parse(&mut self) -> io::Result<Option<Input>> {
+        loop {
+            match self.received.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.received.remove(0);
+                    return Ok(Some(Input::Interrupt));
+                }
+                Some(b'$') => {
+                    let Some(end) = self.received.iter().position(|&b| b == b'#') else {
+                        return Ok(None);
+                    };
+                    if self.received.len() < end + 3 {
+                        return Ok(None);
+                    }
+                    let body = &self.received[1..end];
+                    let sum = body.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+                    let valid = std::str::from_utf8(&self.received[end + 1..end + 3])
+                        .ok()
+                        .and_then(|cs| u8::from_str_radix(cs, 16).ok())
+                        == Some(sum);
+                    let packet = String::from_utf8_lossy(body).into_owned();
+                    self.received.drain(..end + 3);
+                    if !self.no_ack {
+                        self.stream.write_all(if valid { b"+" } else { b"-" })?;
+                    }
+                    if valid {
+                        return Ok(Some(Input::Packet(packet)));
+                    }
+                }
+                Some(_) => {
+                    self.received.remove(0);
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; 1024];
+        let n = self.stream.read(&mut chunk)?;
+        self.received.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    // blocks for the next packet or interrupt, None once gdb hangs up
+    fn next(&mut self) -> io::Result<Option<Input>> {
+        loop {
+            if let Some(input) = self.parse()? {
+                return Ok(Some(input));
+            }
+            if self.fill()? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    // whatever has already arrived, without waiting
+    fn poll(&mut self) -> io::Result<Option<Input>> {
+        self.stream.set_nonblocking(true)?;
+        let filled = match self.fill() {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            other => other.map(|_| ()),
+        };
+        self.stream.set_nonblocking(false)?;
+        filled?;
+        self.parse()
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut body = Vec::with_capacity(data.len());
+        for &b in data.as_bytes() {
+            if matches!(b, b'$' | b'#' | b'}' | b'*') {
+                body.extend_from_slice(&[b'}', b ^ 0x20]);
+            } else {
+                body.push(b);
+            }
+        }
+        let sum = body.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(&body);
+        packet.extend_from_slice(format!("#{sum:02x}").as_bytes());
+        self.stream.write_all(&packet)
+    }
+}
+
+#[derive(Debug)]
+pub struct GdbServer {
+    vm: VM,
+    breakpoints: BTreeSet<u16>,
+    signal: u8, // why the target last stopped
+    symbols: Option<Symbols>,
+    search: Option<RamSearch>,
+}
+
+impl GdbServer {
+    #[must_use]
+    pub fn new(vm: VM) -> Self {
+        Self {
+            vm,
+            breakpoints: BTreeSet::new(),
+            signal: SIGTRAP,
+            symbols: None,
+            search: None,
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    #[must_use]
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    #[must_use]
+    pub fn into_vm(self) -> VM {
+        self.vm
+    }
+
+    #[must_use]
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    // Serves one debugger until it detaches, kills the target or disconnects.
+    pub fn run(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream,
+            received: Vec::new(),
+            no_ack: false,
+        };
+        while let Some(input) = conn.next()? {
+            let Input::Packet(packet) = input else {
+                // already stopped, but gdb still wants to hear about it
+                conn.send(&format!("S{SIGINT:02x}"))?;
+                continue;
+            };
+            match packet.as_str() {
+                "D" => return conn.send("OK"),
+                "k" => return Ok(()),
+                "vKill;1" | "vKill" => {
+                    conn.send("OK")?;
+                    return Ok(());
+                }
+                "QStartNoAckMode" => {
+                    conn.send("OK")?;
+                    conn.no_ack = true;
+                }
+                _ => {
+                    let reply = self.handle(&mut conn, &packet)?;
+                    conn.send(&reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // reply to a packet, an empty reply telling gdb the command is unsupported
+    fn handle(&mut self, conn: &mut Connection, packet: &str) -> io::Result<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{:02x}", self.signal),
+            "g" => hex_encode(
+                &(0..REGISTERS)
+                    .flat_map(|n| self.register_bytes(n))
+                    .collect::<Vec<u8>>(),
+            ),
+            "G" => self.write_registers(args),
+            "p" => match parse_hex(args).filter(|&n| n < REGISTERS) {
+                Some(n) => hex_encode(&self.register_bytes(n)),
+                None => "E01".to_string(),
+            },
+            "P" => self.write_register(args),
+            "m" => match parse_range(args) {
+                Some((address, len)) if usize::from(address) < self.vm.memory().len() => {
+                    hex_encode(self.vm.read_memory(address, len))
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "s" => {
+                self.resume_at(args);
+                self.signal = match self.vm.step() {
+                    Ok(_) => SIGTRAP,
+                    Err(fault) => fault_signal(fault),
+                };
+                format!("S{:02x}", self.signal)
+            }
+            "c" => {
+                self.resume_at(args);
+                self.signal = self.resume(conn)?;
+                format!("S{:02x}", self.signal)
+            }
+            "H" => "OK".to_string(),
+            "q" => match args.strip_prefix("Rcmd,") {
+                Some(command) => self.monitor(command),
+                None => query(args),
+            },
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    // `monitor` commands arrive hex encoded, and so does their output
+    fn monitor(&mut self, hex: &str) -> String {
+        let Some(command) = hex_decode(hex).and_then(|b| String::from_utf8(b).ok()) else {
+            return "E01".to_string();
+        };
+        let symbols = self.symbols.clone().unwrap_or_default();
+        let (name, arg) = command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""));
+        let output = match (name, symbols.resolve(arg)) {
+            ("break", Some(address)) => {
+                self.breakpoints.insert(address);
+                format!(
+                    "Breakpoint at {address:#06X} ({})\n",
+                    symbols.describe(address)
+                )
+            }
+            ("delete", Some(address)) => {
+                self.breakpoints.remove(&address);
+                format!("Deleted breakpoint at {address:#06X}\n")
+            }
+            ("break" | "delete", None) => format!("No label or address {arg:?}\n"),
+            ("where", _) => {
+                let pc = self.vm.program_counter();
+                let mut out = format!("{pc:#06X}  {}\n", describe(self.symbols.as_ref(), pc));
+                for &ret in self.vm.stack().iter().rev() {
+                    let site = ret.wrapping_sub(2);
+                    let _ = writeln!(
+                        out,
+                        "{site:#06X}  {}",
+                        describe(self.symbols.as_ref(), site)
+                    );
+                }
+                out
+            }
+            ("cheat" | "cheats", _) => self.cheat(arg),
+            ("search", _) => self.search(arg),
+            _ => "Commands: break LABEL, delete LABEL, where, cheats, \
+                  cheat ADDRESS|VX = VALUE [NAME], cheat on|off|delete N, \
+                  search [equal VALUE|changed|unchanged|increased|decreased]\n"
+                .to_string(),
+        };
+        hex_encode(output.as_bytes())
+    }
+
+    // lists the cheats, adds one in cheat file syntax, or switches one on or off
+    fn cheat(&mut self, args: &str) -> String {
+        let cheats = self.vm.cheats_mut();
+        let (action, n) = args.split_once(' ').unwrap_or((args, ""));
+        let n = n.trim().parse::<usize>().ok();
+        let done = match (action, n) {
+            ("" | "list", _) => true,
+            ("on", Some(n)) => cheats.set_enabled(n, true),
+            ("off", Some(n)) => cheats.set_enabled(n, false),
+            ("delete", Some(n)) => cheats.remove(n).is_some(),
+            _ => match Cheats::parse(args) {
+                Ok(new) => {
+                    new.iter().cloned().for_each(|c| cheats.add(c));
+                    true
+                }
+                Err(e) => return format!("{e}\n"),
+            },
+        };
+        if !done {
+            return format!("No cheat {args:?}\n");
+        }
+        let mut out = String::new();
+        for (n, cheat) in cheats.iter().enumerate() {
+            let _ = writeln!(out, "{n:>2}  {cheat}");
+        }
+        out
+    }
+
+    // starts a RAM search, or narrows the current one, and shows what's left
+    fn search(&mut self, args: &str) -> String {
+        let filter = match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] | ["new"] => None,
+            ["changed"] => Some(SearchFilter::Changed),
+            ["unchanged"] => Some(SearchFilter::Unchanged),
+            ["increased"] => Some(SearchFilter::Increased),
+            ["decreased"] => Some(SearchFilter::Decreased),
+            ["equal", value] => match parse_hex(value).and_then(|v| u8::try_from(v).ok()) {
+                Some(value) => Some(SearchFilter::Equal(value)),
+                None => return format!("Bad value {value:?}\n"),
+            },
+            _ => return format!("Unknown search {args:?}\n"),
+        };
+        let search = match (filter, &mut self.search) {
+            (Some(filter), Some(search)) => {
+                search.filter(&self.vm, filter);
+                search
+            }
+            (None, _) | (_, None) => self.search.insert(RamSearch::new(&self.vm)),
+        };
+        let candidates = search.candidates();
+        let mut out = format!("{} candidates\n", candidates.len());
+        for &address in candidates.iter().take(16) {
+            let value = self.vm.read_memory(address, 1)[0];
+            let _ = writeln!(out, "{address:#06X}  {value:02X}");
+        }
+        out
+    }
+
+    fn register_bytes(&self, n: usize) -> Vec<u8> {
+        let vm = &self.vm;
+        match n {
+            I => vm.index().to_be_bytes().to_vec(),
+            PC => vm.program_counter().to_be_bytes().to_vec(),
+            SP => vec![vm.stack().len() as u8],
+            DT => vec![vm.delay_timer()],
+            ST => vec![vm.sound_timer()],
+            v => vec![vm.register(v as u8)],
+        }
+    }
+
+    fn set_register_bytes(&mut self, n: usize, bytes: &[u8]) {
+        let word = || u16::from_be_bytes([bytes[0], bytes[1]]);
+        let vm = &mut self.vm;
+        match n {
+            I => vm.set_index(word()),
+            PC => vm.set_program_counter(word()),
+            SP => {
+                // shrinking drops the newest entries, growing pushes zeros
+                let mut stack = vm.stack().to_vec();
+                stack.resize(usize::from(bytes[0]), 0);
+                vm.set_stack(&stack);
+            }
+            DT => vm.set_timers(bytes[0], vm.sound_timer()),
+            ST => vm.set_timers(vm.delay_timer(), bytes[0]),
+            v => vm.set_register(v as u8, bytes[0]),
+        }
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let Some(bytes) = hex_decode(hex) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != (0..REGISTERS).map(register_size).sum::<usize>() {
+            return "E01".to_string();
+        }
+        let mut offset = 0;
+        for n in 0..REGISTERS {
+            let size = register_size(n);
+            self.set_register_bytes(n, &bytes[offset..offset + size]);
+            offset += size;
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let Some((n, value)) = args.split_once('=') else {
+            return "E01".to_string();
+        };
+        match (parse_hex(n), hex_decode(value)) {
+            (Some(n), Some(bytes)) if n < REGISTERS && bytes.len() == register_size(n) => {
+                self.set_register_bytes(n, &bytes);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        match (parse_range(range), hex_decode(data)) {
+            (Some((address, len)), Some(bytes))
+                if bytes.len() == len && self.vm.write_memory(address, &bytes) == len =>
+            {
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    // Z0,addr,kind and z0,addr,kind
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let Some((kind, rest)) = args.split_once(',') else {
+            return "E01".to_string();
+        };
+        if kind != "0" && kind != "1" {
+            return String::new();
+        }
+        let Some((address, _)) = parse_range(rest) else {
+            return "E01".to_string();
+        };
+        if insert {
+            self.breakpoints.insert(address);
+        } else {
+            self.breakpoints.remove(&address);
+        }
+        "OK".to_string()
+    }
+
+    // `s addr` and `c addr` resume from a new PC
+    fn resume_at(&mut self, args: &str) {
+        if let Some(address) = parse_hex(args).and_then(|a| u16::try_from(a).ok()) {
+            self.vm.set_program_counter(address);
+        }
+    }
+
+    // Runs frames in real time until a breakpoint, fault or interrupt and returns
+    // the signal to report. Packets other than Ctrl-C arriving meanwhile are dropped.
+    fn resume(&mut self, conn: &mut Connection) -> io::Result<u8> {
+        // step off the breakpoint we are sitting on, or it would stop us straight away
+        if self.breakpoints.contains(&self.vm.program_counter()) {
+            if let Err(fault) = self.vm.step() {
+                return Ok(fault_signal(fault));
+            }
+        }
+        let mut next_frame = Instant::now();
+        loop {
+            let breakpoints = &self.breakpoints;
+            match self
+                .vm
+                .run_frame_until(|vm| breakpoints.contains(&vm.program_counter()))
+            {
+                Ok(true) => return Ok(SIGTRAP),
+                Ok(false) => {}
+                Err(fault) => return Ok(fault_signal(fault)),
+            }
+            if let Some(Input::Interrupt) = conn.poll()? {
+                return Ok(SIGINT);
+            }
+            next_frame += FRAME;
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hex_decode, hex_encode, GdbServer};
+    use crate::symbols::Symbols;
+    use crate::VM;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::JoinHandle;
+
+    // a scripted gdb
+    struct Client {
+        stream: TcpStream,
+        server: JoinHandle<GdbServer>,
+    }
+
+    impl Client {
+        fn start(program: &[u8]) -> Self {
+            Self::start_with_symbols(program, None)
+        }
+
+        fn start_with_symbols(program: &[u8], symbols: Option<Symbols>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let mut vm = VM::new();
+            vm.load_bytes(program, 0x200).unwrap();
+            let server = std::thread::spawn(move || {
+                let mut server = GdbServer::new(vm);
+                if let Some(symbols) = symbols {
+                    server.set_symbols(symbols);
+                }
+                server.run(listener.accept().unwrap().0).unwrap();
+                server
+            });
+            let stream = TcpStream::connect(address).unwrap();
+            let mut client = Self { stream, server };
+            assert_eq!(client.command("QStartNoAckMode"), "OK");
+            client
+        }
+
+        fn send(&mut self, packet: &str) {
+            let sum = packet.bytes().fold(0u8, u8::wrapping_add);
+            write!(self.stream, "${packet}#{sum:02x}").unwrap();
+        }
+
+        fn reply(&mut self) -> String {
+            let mut packet = vec![];
+            let mut byte = [0];
+            while !packet.ends_with(b"#") {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'$' {
+                    packet.clear();
+                } else {
+                    packet.push(byte[0]);
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            packet.pop();
+            String::from_utf8(packet).unwrap()
+        }
+
+        fn command(&mut self, packet: &str) -> String {
+            self.send(packet);
+            self.reply()
+        }
+
+        fn monitor(&mut self, command: &str) -> String {
+            let reply = self.command(&format!("qRcmd,{}", hex_encode(command.as_bytes())));
+            String::from_utf8(hex_decode(&reply).unwrap()).unwrap()
+        }
+
+        fn detach(mut self) -> GdbServer {
+            assert_eq!(self.command("D"), "OK");
+            self.server.join().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut gdb = Client::start(&[0x60, 0x2A]);
+        assert_eq!(gdb.command("?"), "S05");
+        assert_eq!(
+            gdb.command("g"),
+            format!("{}00000200000000", "00".repeat(16))
+        );
+        assert_eq!(gdb.command("s"), "S05");
+        assert_eq!(gdb.command("p0"), "2a");
+        assert_eq!(gdb.command("P10=0300"), "OK");
+        assert_eq!(gdb.command("p10"), "0300");
+        assert_eq!(gdb.command("P13=3c"), "OK");
+        assert_eq!(gdb.command("P12=02"), "OK");
+        assert_eq!(gdb.command("P11=0"), "E01");
+
+        assert_eq!(gdb.command("m200,2"), "602a");
+        assert_eq!(gdb.command("M300,2:abcd"), "OK");
+        assert_eq!(gdb.command("m300,2"), "abcd");
+        assert_eq!(gdb.command("mffff,1"), "E01");
+        assert!(gdb
+            .command("qXfer:features:read:target.xml:0,1000")
+            .contains("name=\"pc\""));
+
+        let vm = gdb.detach().into_vm();
+        assert_eq!(vm.index(), 0x300);
+        assert_eq!(vm.delay_timer(), 0x3C);
+        assert_eq!(vm.stack(), &[0, 0]);
+        assert_eq!(vm.read_memory(0x300, 2), &[0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn test_breakpoints_step_and_continue() {
+        // ADD V0, 1; ADD V1, 1; JMP 0x200
+        let mut gdb = Client::start(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        assert_eq!(gdb.command("Z0,202,2"), "OK");
+        assert_eq!(gdb.command("c"), "S05");
+        assert_eq!(
+            (gdb.command("p11"), gdb.command("p0")),
+            ("0202".into(), "01".into())
+        );
+        assert_eq!(gdb.command("c"), "S05");
+        assert_eq!(
+            (gdb.command("p11"), gdb.command("p0")),
+            ("0202".into(), "02".into())
+        );
+        assert_eq!(gdb.command("s"), "S05");
+        assert_eq!(gdb.command("p11"), "0204");
+        assert_eq!(gdb.command("z0,202,2"), "OK");
+        assert_eq!(gdb.command("Z2,202,2"), "");
+        assert!(gdb.detach().breakpoints().is_empty());
+    }
+
+    #[test]
+    fn test_monitor_with_symbols() {
+        // 0x200 CALL 0x204; (pad); 0x204 ADD V0, 1; RET
+        let symbols = Symbols::parse("0x200 main\n0x204 bump").unwrap();
+        let program = [0x22, 0x04, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE];
+        let mut gdb = Client::start_with_symbols(&program, Some(symbols));
+        assert!(gdb.monitor("help").starts_with("Commands"));
+        assert_eq!(
+            gdb.monitor("break bump+2"),
+            "Breakpoint at 0x0206 (bump+0x02)\n"
+        );
+        assert_eq!(
+            gdb.monitor("break nowhere"),
+            "No label or address \"nowhere\"\n"
+        );
+        assert_eq!(gdb.command("c"), "S05");
+        assert_eq!(gdb.monitor("where"), "0x0206  bump+0x02\n0x0200  main\n");
+        assert!(gdb.detach().breakpoints().contains(&0x206));
+    }
+
+    #[test]
+    fn test_monitor_cheats_and_search() {
+        // ADD V0, 1; LD I, 0x300; LD [I], V0; JMP 0x200
+        let mut gdb = Client::start(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]);
+        assert_eq!(gdb.command("Z0,206,2"), "OK");
+        assert_eq!(gdb.command("c"), "S05");
+        assert_eq!(
+            gdb.monitor("search").lines().next(),
+            Some("4096 candidates")
+        );
+        assert_eq!(gdb.command("c"), "S05");
+        assert_eq!(
+            gdb.monitor("search increased"),
+            "1 candidates\n0x0300  02\n"
+        );
+        assert_eq!(gdb.monitor("search equal 3"), "0 candidates\n");
+        assert_eq!(gdb.monitor("search maybe"), "Unknown search \"maybe\"\n");
+
+        assert_eq!(
+            gdb.monitor("cheat 0x300 = 7F count"),
+            " 0  0x0300 = 7F  count\n"
+        );
+        assert_eq!(
+            gdb.monitor("cheat V1 = 1"),
+            " 0  0x0300 = 7F  count\n 1  V1 = 01  V1\n"
+        );
+        assert_eq!(
+            gdb.monitor("cheat off 1"),
+            " 0  0x0300 = 7F  count\n 1  !V1 = 01  V1\n"
+        );
+        assert_eq!(gdb.monitor("cheat delete 5"), "No cheat \"delete 5\"\n");
+        assert!(gdb.monitor("cheat lives").starts_with("line 1: expected"));
+        let vm = gdb.detach().into_vm();
+        assert_eq!(vm.cheats().len(), 2);
+    }
+
+    #[test]
+    fn test_interrupt_and_fault() {
+        // JMP 0x200, and an unknown opcode at 0x300
+        let mut gdb = Client::start(&[0x12, 0x00]);
+        gdb.send("c");
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.reply(), "S02");
+        assert_eq!(gdb.command("?"), "S02");
+
+        assert_eq!(gdb.command("M300,2:ffff"), "OK");
+        assert_eq!(gdb.command("c300"), "S04");
+        assert_eq!(gdb.command("p11"), "0300");
+        gdb.detach();
+    }
+}
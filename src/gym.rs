@@ -0,0 +1,258 @@
+// A gym-style environment for training agents on CHIP-8 games, headless and
+// deterministic. Rewards and episode ends are read from the game's memory:
+//
+//     let mut env = Env::new(VM::new())
+//         .frame_skip(4)
+//         .reward(Probe::Bcd(0x3F0, 3), 1.0) // score, as the game's BCD digits
+//         .done_when(Probe::Byte(0x3F5), Compare::Equal, 0) // lives
+//         .max_frames(10_000);
+//     let mut observation = env.reset(&rom, seed)?;
+//     loop {
+//         let (next, reward, done) = env.step(keys_from(&observation));
+//         ...
+//     }
+//
+// An `Env` is a plain value, so cloning one forks the game at that point, and
+// clones can be stepped on separate threads.
+use crate::{Fault, Framebuffer, VM};
+use std::io;
+
+// a number the game keeps in memory or a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    Byte(u16),
+    Word(u16),    // big-endian, as CHIP-8 code stores 16 bit values
+    Bcd(u16, u8), // that many decimal digits, one per byte, as FX33 writes them
+    Register(u8), // V0-VF
+}
+
+impl Probe {
+    #[must_use]
+    pub fn read(&self, vm: &VM) -> i64 {
+        let byte =
+            |address: u16| i64::from(vm.read_memory(address, 1).first().copied().unwrap_or(0));
+        match *self {
+            Probe::Byte(address) => byte(address),
+            Probe::Word(address) => byte(address) << 8 | byte(address.wrapping_add(1)),
+            Probe::Bcd(address, digits) => {
+                (0..u16::from(digits)).fold(0, |n, d| n * 10 + byte(address.wrapping_add(d)))
+            }
+            Probe::Register(register) => i64::from(vm.register(register & 0xF)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Compare {
+    fn test(self, a: i64, b: i64) -> bool {
+        match self {
+            Compare::Equal => a == b,
+            Compare::NotEqual => a != b,
+            Compare::Less => a < b,
+            Compare::Greater => a > b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Env {
+    template: VM, // configured machine each episode starts from
+    vm: VM,
+    frame_skip: u32,
+    rewards: Vec<(Probe, f64)>, // reward is scale times how much the probe went up
+    done: Vec<(Probe, Compare, i64)>,
+    max_frames: Option<u64>,
+    last: Vec<i64>, // reward probes as of the previous step
+    frame: u64,
+    fault: Option<Fault>,
+}
+
+impl Env {
+    // `vm` sets up timing, quirks and memory for every episode
+    #[must_use]
+    pub fn new(vm: VM) -> Self {
+        Self {
+            template: vm.clone(),
+            vm,
+            frame_skip: 1,
+            rewards: vec![],
+            done: vec![],
+            max_frames: None,
+            last: vec![],
+            frame: 0,
+            fault: None,
+        }
+    }
+
+    // frames each step holds the keys for, summing the reward over them
+    #[must_use]
+    pub fn frame_skip(mut self, frames: u32) -> Self {
+        self.frame_skip = frames.max(1);
+        self
+    }
+
+    #[must_use]
+    pub fn reward(mut self, probe: Probe, scale: f64) -> Self {
+        self.rewards.push((probe, scale));
+        self
+    }
+
+    // ends the episode once any of these conditions holds after a frame
+    #[must_use]
+    pub fn done_when(mut self, probe: Probe, compare: Compare, value: i64) -> Self {
+        self.done.push((probe, compare, value));
+        self
+    }
+
+    #[must_use]
+    pub fn max_frames(mut self, frames: u64) -> Self {
+        self.max_frames = Some(frames);
+        self
+    }
+
+    // starts an episode of `rom` with its random numbers drawn from `seed`
+    pub fn reset(&mut self, rom: &[u8], seed: u64) -> io::Result<Framebuffer> {
+        self.vm = self.template.clone();
+        self.vm.load_bytes(rom, 0x200)?;
+        self.vm.seed(seed);
+        self.last = self.rewards.iter().map(|(p, _)| p.read(&self.vm)).collect();
+        self.frame = 0;
+        self.fault = None;
+        Ok(*self.vm.framebuffer())
+    }
+
+    // Holds `keys` (bit k for key k) for up to `frame_skip` frames, returning the
+    // screen, the reward earned and whether the episode is over. A fault ends it.
+    pub fn step(&mut self, keys: u16) -> (Framebuffer, f64, bool) {
+        for key in 0..16 {
+            self.vm.set_key(key, keys & 1 << key != 0);
+        }
+        let mut done = self.is_done();
+        for _ in 0..self.frame_skip {
+            if done {
+                break;
+            }
+            if let Err(fault) = self.vm.run_frame() {
+                self.fault = Some(fault);
+            }
+            self.frame += 1;
+            done = self.is_done();
+        }
+
+        let mut reward = 0.0;
+        for ((probe, scale), last) in self.rewards.iter().zip(&mut self.last) {
+            let value = probe.read(&self.vm);
+            reward += scale * (value - *last) as f64;
+            *last = value;
+        }
+        (*self.vm.framebuffer(), reward, done)
+    }
+
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.fault.is_some()
+            || self.max_frames.is_some_and(|max| self.frame >= max)
+            || self
+                .done
+                .iter()
+                .any(|(probe, compare, value)| compare.test(probe.read(&self.vm), *value))
+    }
+
+    #[must_use]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // what ended the episode, if the game crashed
+    #[must_use]
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    #[must_use]
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Compare, Env, Probe};
+    use crate::{Fault, VM};
+
+    // counts up at 0x300 until key 0 is pressed, then sets V3 and halts
+    const COUNTER: [u8; 14] = [
+        0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0xE1, 0x9E, 0x12, 0x00, 0x63, 0x01, 0x12, 0x0C,
+    ];
+
+    // draws the font's 0 at random places forever
+    const SCATTER: [u8; 8] = [0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x15, 0x12, 0x00];
+
+    #[test]
+    fn test_rewards_and_done() {
+        let mut env = Env::new(VM::new())
+            .frame_skip(2)
+            .reward(Probe::Byte(0x300), 0.5)
+            .done_when(Probe::Register(3), Compare::Equal, 1);
+        assert_eq!(env.reset(&COUNTER, 1).unwrap().to_bytes(), vec![0; 64 * 32]);
+
+        let (_, reward, done) = env.step(0);
+        assert!(!done);
+        assert!((reward - 0.5 * f64::from(env.vm().memory()[0x300])).abs() < 1e-9);
+        assert_eq!(env.frame(), 2);
+
+        let (_, _, done) = env.step(1);
+        assert!(done);
+        assert_eq!(env.vm().register(3), 1);
+        let frame = env.frame();
+        assert!(env.step(0).2);
+        assert_eq!(env.frame(), frame);
+
+        let mut env = env.max_frames(3);
+        env.reset(&COUNTER, 1).unwrap();
+        assert!(!env.step(0).2);
+        assert!(env.step(0).2);
+        assert_eq!(env.frame(), 3);
+
+        env.reset(&[0x00, 0xEE], 1).unwrap();
+        assert!(env.step(0).2);
+        assert_eq!(env.fault(), Some(Fault::StackUnderflow));
+    }
+
+    #[test]
+    fn test_seeds_and_clones() {
+        let mut env = Env::new(VM::new()).frame_skip(4);
+        let start = env.reset(&SCATTER, 7).unwrap();
+        let a: Vec<_> = (0..5).map(|_| env.step(0).0).collect();
+        assert_eq!(env.reset(&SCATTER, 7).unwrap(), start);
+        let b: Vec<_> = (0..5).map(|_| env.step(0).0).collect();
+        assert_eq!(a, b);
+        env.reset(&SCATTER, 8).unwrap();
+        assert_ne!(env.step(0).0, a[0]);
+
+        // forks of one game carry on identically on their own threads
+        env.reset(&SCATTER, 9).unwrap();
+        env.step(0);
+        let mut forks = vec![env.clone(); 8];
+        let expected = (0..10).map(|_| env.step(0).0).last();
+        std::thread::scope(|s| {
+            for fork in &mut forks {
+                s.spawn(|| {
+                    let screen = (0..10).map(|_| fork.step(0).0).last();
+                    assert_eq!(screen, expected);
+                });
+            }
+        });
+    }
+}
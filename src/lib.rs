@@ -4,17 +4,39 @@
 #![allow(clippy::cast_precision_loss)]
 #![allow(clippy::cast_lossless)]
 #![allow(clippy::cast_possible_truncation)]
+pub mod batch;
+pub mod config;
+pub mod dap;
+pub mod database;
+pub mod gdb;
+pub mod gym;
+pub mod linemap;
+pub mod patch;
+pub mod script;
+pub mod symbols;
+pub mod terminal;
+pub mod trace;
 mod vm;
+pub mod watch;
 use macroquad::audio::Sound;
 use std::sync::OnceLock;
 pub use vm::OpCode;
+pub use vm::KEYMAP;
 pub use vm::VM;
+pub use vm::{scale_rgba, RGBA_LEN};
+pub use vm::{
+    CallStats, Cheat, Cheats, Coverage, Fault, Filter, Framebuffer, MemoryMode, Palette, Platform,
+    Profiler, QuirkOverrides, Quirks, RamSearch, Recorder, Row, Scaling, SearchFilter, Target,
+    Timing, Viewport,
+};
 
 pub const STACK_SIZE: usize = 16;
 pub const SCREEN_WIDTH: u32 = 64;
 pub const SCREEN_HEIGHT: u32 = 32;
+// Default speed. The run loop before frame timing stepped the CPU whenever 3 µs
+// had passed, about 5,500 instructions a frame, and that is kept; `--ipf` or
+// `--vip` give period speeds.
+pub const INSTRUCTIONS_PER_FRAME: u32 = 5_555;
 pub const WINDOW_WIDTH: u32 = 1024;
 pub const WINDOW_HEIGHT: u32 = 512;
-pub const PIXEL_WIDTH: f32 = WINDOW_WIDTH as f32 / SCREEN_WIDTH as f32;
-pub const PIXEL_HEIGHT: f32 = WINDOW_HEIGHT as f32 / SCREEN_HEIGHT as f32;
 pub static SOUND: OnceLock<Sound> = OnceLock::new();
@@ -0,0 +1,97 @@
+// Address to source line mapping for ROMs built from Octo or assembler source.
+// The file is plain text, one `address line` pair per line:
+//
+//     # breakout.8o
+//     0x200 12
+//     0x202 13
+//     0x30C 40    # sprite data
+//
+// Addresses are hex (with or without 0x), lines are 1-based and decimal. An
+// address without an entry belongs to the closest mapped address below it.
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineMap {
+    lines: BTreeMap<u16, u32>,
+}
+
+impl LineMap {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = BTreeMap::new();
+        for (n, entry) in text.lines().enumerate() {
+            let entry = entry.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let invalid = || format!("line {}: expected `address line`, got {entry:?}", n + 1);
+            let mut fields = entry.split_whitespace();
+            let (Some(address), Some(line), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let address = address.trim_start_matches("0x").trim_start_matches("0X");
+            let address = u16::from_str_radix(address, 16).map_err(|_| invalid())?;
+            let line = line.parse().map_err(|_| invalid())?;
+            lines.insert(address, line);
+        }
+        Ok(Self { lines })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&std::fs::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    // source line an address was assembled from
+    #[must_use]
+    pub fn line(&self, address: u16) -> Option<u32> {
+        self.lines
+            .range(..=address)
+            .next_back()
+            .map(|(_, &line)| line)
+    }
+
+    // addresses mapped directly to a source line, lowest first
+    #[must_use]
+    pub fn addresses(&self, line: u32) -> Vec<u16> {
+        self.lines
+            .iter()
+            .filter(|(_, &l)| l == line)
+            .map(|(&address, _)| address)
+            .collect()
+    }
+
+    // first line at or after `line` that has code, for placing breakpoints on blank lines
+    #[must_use]
+    pub fn nearest_line(&self, line: u32) -> Option<u32> {
+        self.lines.values().filter(|&&l| l >= line).min().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        self.lines.iter().map(|(&address, &line)| (address, line))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LineMap;
+
+    #[test]
+    fn test_parse_line_map() {
+        let map = LineMap::parse("# demo.8o\n0x200 3\n202 4 # comment\n\n0x30C 9\n").unwrap();
+        assert_eq!(map.line(0x200), Some(3));
+        assert_eq!(map.line(0x203), Some(4));
+        assert_eq!(map.line(0x1FF), None);
+        assert_eq!(map.addresses(9), vec![0x30C]);
+        assert_eq!(map.nearest_line(5), Some(9));
+        assert!(LineMap::parse("0x200").is_err());
+        assert!(LineMap::parse("zz 1").is_err());
+    }
+}
@@ -1,20 +1,137 @@
-use chip8::{VM, WINDOW_HEIGHT, WINDOW_WIDTH};
+use chip8::config::{Config, ConfigLayer};
+use chip8::database::Rom;
+use chip8::patch;
+use chip8::watch::Watch;
+use chip8::{Cheats, VM, WINDOW_HEIGHT, WINDOW_WIDTH};
 use macroquad::window::Conf;
-#[macroquad::main(conf)]
-async fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
+use std::path::PathBuf;
+
+// Arguments are handled before the window opens, so `--info` and bad input
+// only print.
+fn main() {
+    let mut rom = None;
+    let mut patches = vec![];
+    let mut watch = false;
+    let mut keep_state = false;
+    let mut build = None;
+    let mut source = None;
+    let mut fullscreen = false;
+    let mut info = false;
+    let mut cli = ConfigLayer::default();
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fractional" => cli.scaling = Some("fractional".to_string()),
+            "--fullscreen" => fullscreen = true,
+            "--vip" => cli.timing = Some("vip".to_string()),
+            "--ipf" => cli.cycles_per_frame = args.next().and_then(|n| n.parse().ok()),
+            "--platform" => cli.platform = args.next(),
+            "--keymap" => cli.keymap = args.next(),
+            "--memory" => cli.memory = args.next(),
+            "--blend" => cli.filter = Some("blend".to_string()),
+            "--phosphor" => {
+                cli.filter = Some("phosphor".to_string());
+                // the decay is optional, so a ROM path after the flag is left alone
+                if let Some(decay) = args.peek().and_then(|d| d.parse().ok()) {
+                    cli.phosphor_decay = Some(decay);
+                    args.next();
+                }
+            }
+            "--patch" => patches.extend(args.next().map(PathBuf::from)),
+            "--watch" => watch = true,
+            "--keep-state" => keep_state = true,
+            "--build" => build = args.next(),
+            "--source" => source = args.next().map(PathBuf::from),
+            "--info" => info = true,
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(path) = rom else {
         println!("Please supply ROM file as argument");
+        return;
+    };
+
+    let rom = match patch::load(path.as_ref(), &patches) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("Could not load ROM: {e}");
+            return;
+        }
+    };
+    let config = match Config::resolve(&rom, path.as_ref(), &cli) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Invalid configuration: {e}");
+            return;
+        }
+    };
+    if info {
+        print!("{rom}");
+        for file in &config.files {
+            println!("Config:   {}", file.display());
+        }
+        return;
     }
 
-    let mut vm = VM::new();
+    let options = Options {
+        path,
+        patches,
+        watch,
+        keep_state,
+        build,
+        source,
+        fullscreen,
+        cli,
+    };
+    macroquad::Window::from_config(conf(), run(rom, config, options));
+}
+
+// the rest of the command line, used once the window is up
+struct Options {
+    path: String,
+    patches: Vec<PathBuf>,
+    watch: bool,
+    keep_state: bool,
+    build: Option<String>,
+    source: Option<PathBuf>,
+    fullscreen: bool,
+    cli: ConfigLayer,
+}
+
+async fn run(rom: Rom, config: Config, options: Options) {
+    let Options {
+        path,
+        patches,
+        watch,
+        keep_state,
+        build,
+        source,
+        fullscreen,
+        cli,
+    } = options;
+    let mut vm = VM::from_config(&config);
+    if let Err(e) = vm.load_rom(&rom) {
+        println!("Could not load ROM: {e}");
+        return;
+    }
+    match Cheats::for_rom(&rom, path.as_ref()) {
+        Ok(cheats) => vm.set_cheats(cheats),
+        Err(e) => println!("Invalid cheats, running without them: {e}"),
+    }
+    if fullscreen {
+        vm.toggle_fullscreen();
+    }
     chip8::SOUND
         .set(macroquad::audio::load_sound("buzz.wav").await.unwrap())
         .unwrap();
-    match vm.load_program(&args[1]) {
-        Ok(_) => vm.run().await,
-        Err(e) => panic!("Could not load ROM: {e}"),
-    }
+    let watch = watch.then(|| {
+        let watch = Watch::new(path.as_ref(), &patches, cli).keep_state(keep_state);
+        match &build {
+            Some(command) => watch.build(command, source.as_deref()),
+            None => watch,
+        }
+    });
+    vm.run(watch).await;
 }
 
 fn conf() -> Conf {
@@ -22,7 +139,7 @@ fn conf() -> Conf {
         window_title: "CHIP-8".to_string(),
         window_width: WINDOW_WIDTH as i32,
         window_height: WINDOW_HEIGHT as i32,
-        window_resizable: false,
+        window_resizable: true,
         ..Default::default()
     }
 }
@@ -0,0 +1,361 @@
+// IPS and BPS patches, as fan translations and bug fixes are distributed. Patches
+// given on the command line are applied to the ROM image in order, then any that
+// sit next to the ROM as `game.ch8.ips` or `game.ch8.bps`. BPS patches carry
+// CRC32s of the ROM they expect, the result and themselves, and all three are
+// checked. IPS has no checksums, so the patch is applied to whatever it's given.
+use crate::config::sidecar;
+use crate::database::Rom;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const IPS_MAX_RECORD: usize = 0xFFFF;
+const BPS_MAGIC: &[u8] = b"BPS1";
+// Larger than any ROM that could be loaded, so a patch claiming more is refused
+// before anything is allocated for it
+const MAX_ROM: usize = 0x10000;
+
+// CRC-32 as used by zip and BPS
+#[must_use]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &b| {
+        (0..8).fold(crc ^ u32::from(b), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+fn take<'a>(patch: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let end = pos.checked_add(len).ok_or("patch ends unexpectedly")?;
+    let bytes = patch.get(*pos..end).ok_or("patch ends unexpectedly")?;
+    *pos = end;
+    Ok(bytes)
+}
+
+fn big_endian(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, &b| n << 8 | usize::from(b))
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut pos = IPS_MAGIC.len();
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err("not an IPS patch".to_string());
+    }
+    let mut out = rom.to_vec();
+    loop {
+        let offset = take(patch, &mut pos, 3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = big_endian(offset);
+        let size = big_endian(take(patch, &mut pos, 2)?);
+        let (size, fill) = if size == 0 {
+            // run-length record: a count and the byte to repeat
+            let size = big_endian(take(patch, &mut pos, 2)?);
+            (size, Some(take(patch, &mut pos, 1)?[0]))
+        } else {
+            (size, None)
+        };
+        if offset + size > MAX_ROM {
+            return Err(format!(
+                "patched ROM would be {} bytes, too large",
+                offset + size
+            ));
+        }
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        match fill {
+            Some(byte) => out[offset..offset + size].fill(byte),
+            None => out[offset..offset + size].copy_from_slice(take(patch, &mut pos, size)?),
+        }
+    }
+    // a common extension: the size to truncate the result to follows EOF
+    if let Ok(size) = take(patch, &mut pos, 3) {
+        out.truncate(big_endian(size));
+    }
+    Ok(out)
+}
+
+// IPS patch turning `original` into `modified`, one record per run of changed bytes
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, String> {
+    // an offset of 0x454F46 would read as "EOF"
+    if modified.len() >= big_endian(IPS_EOF) {
+        return Err("ROM too large for IPS".to_string());
+    }
+    let differs = |i: usize| original.get(i) != Some(&modified[i]);
+    let mut patch = IPS_MAGIC.to_vec();
+    let mut i = 0;
+    while i < modified.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < modified.len() && differs(i) && i - start < IPS_MAX_RECORD {
+            i += 1;
+        }
+        patch.extend_from_slice(&start.to_be_bytes()[size_of::<usize>() - 3..]);
+        patch.extend_from_slice(&(i - start).to_be_bytes()[size_of::<usize>() - 2..]);
+        patch.extend_from_slice(&modified[start..i]);
+    }
+    patch.extend_from_slice(IPS_EOF);
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&modified.len().to_be_bytes()[size_of::<usize>() - 3..]);
+    }
+    Ok(patch)
+}
+
+// BPS variable-length number: 7 bits a byte, the last byte has its top bit set
+fn bps_number(patch: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let (mut n, mut shift) = (0usize, 1usize);
+    loop {
+        let byte = take(patch, pos, 1)?[0];
+        n = usize::from(byte & 0x7F)
+            .checked_mul(shift)
+            .and_then(|bits| n.checked_add(bits))
+            .ok_or("bad number in patch")?;
+        if byte & 0x80 != 0 {
+            return Ok(n);
+        }
+        shift = shift.checked_mul(0x80).ok_or("bad number in patch")?;
+        n = n.checked_add(shift).ok_or("bad number in patch")?;
+    }
+}
+
+fn little_endian(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// moves a copy offset by the signed amount encoded in the patch
+fn relative(offset: usize, patch: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let data = bps_number(patch, pos)?;
+    let delta = data >> 1;
+    if data & 1 == 0 {
+        offset.checked_add(delta)
+    } else {
+        offset.checked_sub(delta)
+    }
+    .ok_or_else(|| "copy outside the ROM".to_string())
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(BPS_MAGIC) || patch.len() < BPS_MAGIC.len() + 12 {
+        return Err("not a BPS patch".to_string());
+    }
+    let end = patch.len() - 12;
+    let footer = &patch[end..];
+    if crc32(&patch[..patch.len() - 4]) != little_endian(&footer[8..]) {
+        return Err("patch is corrupt (checksum mismatch)".to_string());
+    }
+    if crc32(rom) != little_endian(&footer[..4]) {
+        return Err("patch is for a different ROM (checksum mismatch)".to_string());
+    }
+
+    let mut pos = BPS_MAGIC.len();
+    let source_size = bps_number(patch, &mut pos)?;
+    let target_size = bps_number(patch, &mut pos)?;
+    let metadata = bps_number(patch, &mut pos)?;
+    take(patch, &mut pos, metadata)?;
+    if source_size != rom.len() {
+        return Err("patch is for a ROM of a different size".to_string());
+    }
+    if target_size > MAX_ROM {
+        return Err(format!(
+            "patched ROM would be {target_size} bytes, too large"
+        ));
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while pos < end {
+        let action = bps_number(patch, &mut pos)?;
+        let len = (action >> 2) + 1;
+        if len > target_size - out.len() {
+            return Err("patch writes past the end of the patched ROM".to_string());
+        }
+        match action & 3 {
+            // bytes from the same place in the ROM
+            0 => out.extend_from_slice(
+                rom.get(out.len()..out.len() + len)
+                    .ok_or("read past the end of the ROM")?,
+            ),
+            // bytes stored in the patch
+            1 => out.extend_from_slice(take(&patch[..end], &mut pos, len)?),
+            // bytes from elsewhere in the ROM
+            2 => {
+                source_offset = relative(source_offset, patch, &mut pos)?;
+                let bytes = source_offset
+                    .checked_add(len)
+                    .and_then(|end| rom.get(source_offset..end))
+                    .ok_or("copy outside the ROM")?;
+                out.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // bytes already written, a byte at a time as the ranges may overlap
+            _ => {
+                target_offset = relative(target_offset, patch, &mut pos)?;
+                for _ in 0..len {
+                    let byte = *out.get(target_offset).ok_or("copy outside the output")?;
+                    out.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size || crc32(&out) != little_endian(&footer[4..8]) {
+        return Err("patched ROM doesn't match (checksum mismatch)".to_string());
+    }
+    Ok(out)
+}
+
+// IPS or BPS, going by the patch's header
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        apply_ips(rom, patch)
+    }
+}
+
+// `patches`, then the ROM's `.ips` and `.bps` sidecars that exist
+#[must_use]
+pub fn patches_for(rom_path: &Path, patches: &[PathBuf]) -> Vec<PathBuf> {
+    let sidecars = ["ips", "bps"]
+        .into_iter()
+        .map(|ext| sidecar(rom_path, ext))
+        .filter(|p| p.exists());
+    patches.iter().cloned().chain(sidecars).collect()
+}
+
+// Reads the ROM at `rom_path` with its patches applied. The ROM keeps the hash,
+// and so the database entry and config, of the unpatched file, as a patched game
+// is still the same game.
+pub fn load(rom_path: &Path, patches: &[PathBuf]) -> io::Result<Rom> {
+    let mut rom = Rom::new(std::fs::read(rom_path)?);
+    for path in patches_for(rom_path, patches) {
+        let patch = std::fs::read(&path)?;
+        rom.bytes = apply(&rom.bytes, &patch).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })?;
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply, apply_bps, crc32, create_ips};
+
+    #[test]
+    fn test_ips() {
+        let original = b"HELLO WORLD, THIS IS CHIP-8".to_vec();
+        let mut modified = original.clone();
+        modified[0..2].copy_from_slice(b"JA");
+        modified[13..17].copy_from_slice(b"that");
+        for target in [
+            modified.clone(),
+            [&modified[..], b"!!"].concat(),
+            modified[..5].to_vec(),
+        ] {
+            let patch = create_ips(&original, &target).unwrap();
+            assert_eq!(apply(&original, &patch).unwrap(), target);
+        }
+        assert_eq!(
+            create_ips(&original, &modified).unwrap(),
+            b"PATCH\0\0\0\0\x02JA\0\0\x0D\0\x04thatEOF"
+        );
+
+        // run-length record of 3 bytes of 0xAA at offset 1
+        let rle = b"PATCH\0\0\x01\0\0\0\x03\xAAEOF";
+        assert_eq!(apply(b"ABCDE", rle).unwrap(), b"A\xAA\xAA\xAAE");
+        assert!(apply(b"ABCDE", b"PATCH\0\0\x01\0").is_err());
+        assert!(apply(b"ABCDE", b"nonsense").is_err());
+        // 0xFFFF bytes of 0 at offset 0xFFFFFF
+        let huge = b"PATCH\xFF\xFF\xFF\0\0\xFF\xFF\0EOF";
+        assert!(apply(b"ABCDE", huge).unwrap_err().contains("too large"));
+    }
+
+    fn bps_number(out: &mut Vec<u8>, mut n: usize) {
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            n -= 1;
+        }
+    }
+
+    #[test]
+    fn test_bps() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let source = b"HELLO WORLD";
+        let target = b"HELLO CHIP-8 WORLD!!!!";
+        let mut patch = b"BPS1".to_vec();
+        bps_number(&mut patch, source.len());
+        bps_number(&mut patch, target.len());
+        bps_number(&mut patch, 0);
+        bps_number(&mut patch, (6 - 1) << 2); // source read "HELLO "
+        bps_number(&mut patch, (7 - 1) << 2 | 1); // target read "CHIP-8 "
+        patch.extend_from_slice(b"CHIP-8 ");
+        bps_number(&mut patch, (5 - 1) << 2 | 2); // source copy "WORLD" from 6
+        bps_number(&mut patch, 6 << 1);
+        bps_number(&mut patch, 1 << 2 | 1); // target read "!!"
+        patch.extend_from_slice(b"!!");
+        bps_number(&mut patch, (2 - 1) << 2 | 3); // target copy "!!" from 18
+        bps_number(&mut patch, 18 << 1);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+
+        assert_eq!(apply(source, &patch).unwrap(), target);
+        assert!(apply_bps(b"HELLO THERE", &patch)
+            .unwrap_err()
+            .contains("different ROM"));
+        let last = patch.len() - 13;
+        patch[last] ^= 1;
+        assert!(apply_bps(source, &patch).unwrap_err().contains("corrupt"));
+    }
+
+    // a patch with valid checksums but sizes and lengths meant to exhaust memory
+    #[test]
+    fn test_hostile_bps() {
+        let source = b"HELLO";
+        let hostile = |target_size: usize, action: usize, offset: Option<usize>| {
+            let mut patch = b"BPS1".to_vec();
+            bps_number(&mut patch, source.len());
+            bps_number(&mut patch, target_size);
+            bps_number(&mut patch, 0);
+            bps_number(&mut patch, action);
+            if let Some(offset) = offset {
+                bps_number(&mut patch, offset);
+            }
+            patch.extend_from_slice(&crc32(source).to_le_bytes());
+            patch.extend_from_slice(&[0; 4]);
+            patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+            apply_bps(source, &patch).unwrap_err()
+        };
+        assert!(hostile(1 << 60, 0, None).contains("too large"));
+        assert!(hostile(16, usize::MAX >> 2 << 2 | 3, Some(0)).contains("past the end"));
+        assert!(hostile(16, 3 << 2 | 2, Some(usize::MAX >> 1 << 1)).contains("outside"));
+
+        // a number that never ends would overflow usize before the patch runs out
+        let mut patch = b"BPS1".to_vec();
+        patch.extend_from_slice(&[0x7F; 9]);
+        patch.push(0x80);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&[0; 4]);
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        assert_eq!(
+            apply_bps(source, &patch).unwrap_err(),
+            "bad number in patch"
+        );
+    }
+}
@@ -0,0 +1,510 @@
+// Scripts for driving a ROM and checking its state without writing Rust, run by
+// `chip8-script`. They are Rhai (https://rhai.rs); the top level runs once before
+// the first frame and registers hooks, which run after every frame, before the
+// instruction at an address, or after an instruction writes to memory:
+//
+//     let lives = 3;
+//     on_frame(|| {
+//         if frame() == 120 {
+//             press(5);
+//         }
+//         if frame() == 130 {
+//             release(5);
+//             assert(v(3) == 1, "ball launched");
+//             screenshot("launched.png");
+//             stop();
+//         }
+//     });
+//     // a label from the symbol file, or an address
+//     on_instruction("lose_life", || {
+//         lives -= 1;
+//         print(`lost a life at frame ${frame()}, remaining ${lives}`);
+//     });
+//     // an address, or the first and last address
+//     on_write(0x300, 0x302, |address, value| {
+//         print(`score digit ${address - 0x300} = ${value}`);
+//     });
+//
+// The machine is reached through `v(x)`, `i()`, `pc()`, `sp()`, `dt()`, `st()`,
+// `mem(addr)`, `key(k)` and `pixel(x, y)`, with `set_` versions of all but `sp`,
+// `key` and `pixel`, plus `press(k)`, `release(k)`, `frame()`, `address(label)`
+// and `screenshot(path)`. Registers, keys, addresses and pixels out of range are
+// errors rather than wrapping. A failed assert is recorded and the script carries
+// on, any other error stops it; `stop()` takes effect once the current hook returns.
+use crate::symbols::Symbols;
+use crate::{Fault, SCREEN_HEIGHT, SCREEN_WIDTH, VM};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, Scope, AST};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::rc::Rc;
+
+type Failure = Box<EvalAltResult>;
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    instruction: BTreeMap<u16, Vec<FnPtr>>,
+    write: Vec<(RangeInclusive<u16>, FnPtr)>,
+}
+
+#[derive(Default)]
+struct State {
+    frame: u64,
+    output: Vec<String>,
+    failures: Vec<String>,
+    stopped: bool,
+}
+
+// Script functions can't borrow the caller's VM, so it is swapped into `shared`
+// while `f` runs and swapped back out afterwards.
+fn with_vm<T>(shared: &RefCell<VM>, vm: &mut VM, f: impl FnOnce() -> T) -> T {
+    std::mem::swap(vm, &mut shared.borrow_mut());
+    let result = f();
+    std::mem::swap(vm, &mut shared.borrow_mut());
+    result
+}
+
+// errors raised by the API read `line N: what went wrong`, and an error inside
+// a hook comes wrapped in the call to the hook
+fn message(e: &EvalAltResult) -> String {
+    match e {
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => message(inner),
+        EvalAltResult::ErrorRuntime(value, position) => match position.line() {
+            Some(line) => format!("line {line}: {value}"),
+            None => value.to_string(),
+        },
+        e => e.to_string(),
+    }
+}
+
+fn register(x: i64) -> Result<u8, Failure> {
+    u8::try_from(x)
+        .ok()
+        .filter(|x| *x < 16)
+        .ok_or_else(|| format!("no register V{x}").into())
+}
+
+fn key(k: i64) -> Result<u8, Failure> {
+    u8::try_from(k)
+        .ok()
+        .filter(|k| *k < 16)
+        .ok_or_else(|| format!("no key {k}").into())
+}
+
+// a label or an address
+fn location(symbols: &Symbols, at: &Dynamic) -> Result<u16, Failure> {
+    let address = match at.as_int() {
+        Ok(address) => u16::try_from(address).ok(),
+        Err(_) => symbols.resolve(&at.to_string()),
+    };
+    address.ok_or_else(|| format!("unknown label or address {at:?}").into())
+}
+
+fn outside(address: i64) -> String {
+    format!("address {address:#X} is outside memory")
+}
+
+// registers, memory, keys and the screen
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+fn bind_machine(engine: &mut Engine, vm: &Rc<RefCell<VM>>) {
+    let get = |get: fn(&VM) -> i64| {
+        let vm = vm.clone();
+        move || get(&vm.borrow())
+    };
+    let set = |set: fn(&mut VM, i64)| {
+        let vm = vm.clone();
+        move |value: i64| set(&mut vm.borrow_mut(), value)
+    };
+    engine
+        .register_fn("i", get(|vm| i64::from(vm.index())))
+        .register_fn("set_i", set(|vm, n| vm.set_index(n as u16)))
+        .register_fn("pc", get(|vm| i64::from(vm.program_counter())))
+        .register_fn("set_pc", set(|vm, n| vm.set_program_counter(n as u16)))
+        .register_fn("sp", get(|vm| vm.stack().len() as i64))
+        .register_fn("dt", get(|vm| i64::from(vm.delay_timer())))
+        .register_fn(
+            "set_dt",
+            set(|vm, n| vm.set_timers(n as u8, vm.sound_timer())),
+        )
+        .register_fn("st", get(|vm| i64::from(vm.sound_timer())))
+        .register_fn(
+            "set_st",
+            set(|vm, n| vm.set_timers(vm.delay_timer(), n as u8)),
+        );
+
+    let shared = vm.clone();
+    engine.register_fn("v", move |x: i64| -> Result<i64, Failure> {
+        Ok(i64::from(shared.borrow().register(register(x)?)))
+    });
+    let shared = vm.clone();
+    engine.register_fn("set_v", move |x: i64, value: i64| -> Result<(), Failure> {
+        shared.borrow_mut().set_register(register(x)?, value as u8);
+        Ok(())
+    });
+    let shared = vm.clone();
+    engine.register_fn("mem", move |address: i64| -> Result<i64, Failure> {
+        let vm = shared.borrow();
+        match u16::try_from(address).map(|a| vm.read_memory(a, 1)) {
+            Ok(&[byte]) => Ok(i64::from(byte)),
+            _ => Err(outside(address).into()),
+        }
+    });
+    let shared = vm.clone();
+    engine.register_fn(
+        "set_mem",
+        move |address: i64, value: i64| -> Result<(), Failure> {
+            match u16::try_from(address) {
+                Ok(a) if shared.borrow_mut().write_memory(a, &[value as u8]) == 1 => Ok(()),
+                _ => Err(outside(address).into()),
+            }
+        },
+    );
+    let shared = vm.clone();
+    engine.register_fn("key", move |k: i64| -> Result<bool, Failure> {
+        Ok(shared.borrow().keys()[usize::from(key(k)?)])
+    });
+    for (name, down) in [("press", true), ("release", false)] {
+        let shared = vm.clone();
+        engine.register_fn(name, move |k: i64| -> Result<(), Failure> {
+            shared.borrow_mut().set_key(key(k)?, down);
+            Ok(())
+        });
+    }
+    let shared = vm.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64| -> Result<bool, Failure> {
+        match (u8::try_from(x), u8::try_from(y)) {
+            (Ok(px), Ok(py)) if u32::from(px) < SCREEN_WIDTH && u32::from(py) < SCREEN_HEIGHT => {
+                Ok(shared.borrow().pixel(px, py))
+            }
+            _ => Err(format!("pixel ({x}, {y}) is off the screen").into()),
+        }
+    });
+    let shared = vm.clone();
+    engine.register_fn("screenshot", move |path: &str| -> Result<(), Failure> {
+        shared
+            .borrow()
+            .save_screenshot(path, 8)
+            .map_err(|e| format!("{path}: {e}").into())
+    });
+}
+
+// labels, output, asserts and hooks
+#[allow(clippy::cast_possible_wrap)]
+fn engine(
+    symbols: Symbols,
+    vm: &Rc<RefCell<VM>>,
+    hooks: &Rc<RefCell<Hooks>>,
+    state: &Rc<RefCell<State>>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_strict_variables(true);
+    bind_machine(&mut engine, vm);
+
+    let symbols = Rc::new(symbols);
+    let labels = symbols.clone();
+    engine.register_fn("address", move |at: Dynamic| {
+        location(&labels, &at).map(i64::from)
+    });
+
+    let shared = state.clone();
+    engine.on_print(move |line| shared.borrow_mut().output.push(line.to_string()));
+    let shared = state.clone();
+    engine.register_fn("frame", move || shared.borrow().frame as i64);
+    let shared = state.clone();
+    engine.register_fn("stop", move || shared.borrow_mut().stopped = true);
+    let shared = state.clone();
+    let assert = move |ctx: &NativeCallContext, ok: bool, message: &str| {
+        if !ok {
+            let mut state = shared.borrow_mut();
+            let failure = format!(
+                "line {}: assertion failed at frame {}{message}",
+                ctx.call_position().line().unwrap_or_default(),
+                state.frame
+            );
+            state.failures.push(failure);
+        }
+    };
+    let assert_message = assert.clone();
+    engine.register_fn(
+        "assert",
+        move |ctx: NativeCallContext, ok: bool, message: &str| {
+            assert_message(&ctx, ok, &format!(": {message}"));
+        },
+    );
+    engine.register_fn("assert", move |ctx: NativeCallContext, ok: bool| {
+        assert(&ctx, ok, "");
+    });
+
+    let shared = hooks.clone();
+    engine.register_fn("on_frame", move |hook: FnPtr| {
+        shared.borrow_mut().frame.push(hook);
+    });
+    let shared = hooks.clone();
+    let labels = symbols.clone();
+    engine.register_fn("on_instruction", move |at: Dynamic, hook: FnPtr| {
+        let address = location(&labels, &at)?;
+        let mut hooks = shared.borrow_mut();
+        hooks.instruction.entry(address).or_default().push(hook);
+        Ok::<_, Failure>(())
+    });
+    let on_write = {
+        let hooks = hooks.clone();
+        let vm = vm.clone();
+        move |first: &Dynamic, last: &Dynamic, hook: FnPtr| {
+            let range = location(&symbols, first)?..=location(&symbols, last)?;
+            hooks.borrow_mut().write.push((range, hook));
+            vm.borrow_mut().watch_writes(true);
+            Ok::<_, Failure>(())
+        }
+    };
+    let on_write_one = on_write.clone();
+    engine.register_fn("on_write", move |at: Dynamic, hook: FnPtr| {
+        on_write_one(&at, &at, hook)
+    });
+    engine.register_fn(
+        "on_write",
+        move |first: Dynamic, last: Dynamic, hook: FnPtr| on_write(&first, &last, hook),
+    );
+    engine
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    hooks: Rc<RefCell<Hooks>>,
+    state: Rc<RefCell<State>>,
+    // stands in for the caller's VM between calls into the script
+    vm: Rc<RefCell<VM>>,
+}
+
+impl Script {
+    // `symbols` lets instruction and write hooks name labels instead of addresses
+    pub fn parse(text: &str, symbols: Option<&Symbols>) -> Result<Self, String> {
+        let vm = Rc::new(RefCell::new(VM::new()));
+        let hooks = Rc::default();
+        let state = Rc::default();
+        let engine = engine(symbols.cloned().unwrap_or_default(), &vm, &hooks, &state);
+        let ast = engine.compile(text).map_err(|e| e.to_string())?;
+        Ok(Script {
+            engine,
+            ast,
+            scope: Scope::new(),
+            hooks,
+            state,
+            vm,
+        })
+    }
+
+    pub fn read(path: impl AsRef<Path>, symbols: Option<&Symbols>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&std::fs::read_to_string(path)?, symbols).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    // runs the top level statements, once the ROM is loaded
+    pub fn start(&mut self, vm: &mut VM) {
+        let Script {
+            engine, ast, scope, ..
+        } = self;
+        let result = with_vm(&self.vm, vm, || engine.run_ast_with_scope(scope, ast));
+        if let Err(e) = result {
+            self.fail(&e);
+        }
+    }
+
+    fn fail(&self, e: &Failure) {
+        let mut state = self.state.borrow_mut();
+        state.failures.push(message(e));
+        state.stopped = true;
+    }
+
+    fn call(&self, vm: &mut VM, hook: &FnPtr, args: &[i64]) {
+        if self.is_stopped() {
+            return;
+        }
+        let result = with_vm(&self.vm, vm, || {
+            hook.call::<Dynamic>(&self.engine, &self.ast, args.to_vec())
+        });
+        if let Err(e) = result {
+            self.fail(&e);
+        }
+    }
+
+    fn writes(&self, vm: &mut VM) {
+        for (address, value) in vm.take_writes() {
+            let hooks: Vec<FnPtr> = (self.hooks.borrow().write.iter())
+                .filter(|(range, _)| range.contains(&address))
+                .map(|(_, hook)| hook.clone())
+                .collect();
+            for hook in &hooks {
+                self.call(vm, hook, &[i64::from(address), i64::from(value)]);
+            }
+        }
+    }
+
+    // One frame of the VM with the hooks attached. Does nothing once stopped.
+    pub fn run_frame(&mut self, vm: &mut VM) -> Result<(), Fault> {
+        if self.is_stopped() {
+            return Ok(());
+        }
+        vm.run_frame_until(|vm| {
+            self.writes(vm);
+            let pc = vm.program_counter();
+            let hooks = self.hooks.borrow().instruction.get(&pc).cloned();
+            for hook in hooks.iter().flatten() {
+                self.call(vm, hook, &[]);
+            }
+            false
+        })?;
+        self.writes(vm);
+        self.state.borrow_mut().frame += 1;
+        let hooks = self.hooks.borrow().frame.clone();
+        for hook in &hooks {
+            self.call(vm, hook, &[]);
+        }
+        Ok(())
+    }
+
+    // set by `stop()` or an error
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.state.borrow().stopped
+    }
+
+    #[must_use]
+    pub fn frame(&self) -> u64 {
+        self.state.borrow().frame
+    }
+
+    // failed asserts and errors, in order
+    #[must_use]
+    pub fn failures(&self) -> Vec<String> {
+        self.state.borrow().failures.clone()
+    }
+
+    // lines printed since the last call
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.state.borrow_mut().output)
+    }
+
+    // a top level variable, after `start`
+    #[must_use]
+    pub fn variable(&self, name: &str) -> Option<i64> {
+        self.scope.get_value(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Script;
+    use crate::symbols::Symbols;
+    use crate::{Timing, VM};
+
+    fn vm(program: &[u8]) -> VM {
+        let mut vm = VM::new();
+        vm.set_timing(Timing::Fixed(10));
+        vm.load_bytes(program, 0x200).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut vm = vm(&[]);
+        let mut script = Script::parse(
+            "let a = 2 + 3 * 4 - -1;\nset_v(3, a);\nset_mem(0x300, 0x1FF);\n\
+             let c = mem(0x300) + v(3);\nset_i(0x123);\nset_dt(7);\nlet d = dt();",
+            None,
+        )
+        .unwrap();
+        script.start(&mut vm);
+        assert_eq!(script.variable("a"), Some(15));
+        assert_eq!(script.variable("c"), Some(0xFF + 15));
+        assert_eq!(script.variable("d"), Some(7));
+        assert_eq!((vm.register(3), vm.index()), (15, 0x123));
+        assert!(script.failures().is_empty());
+    }
+
+    #[test]
+    fn test_hooks() {
+        // 0x200 ADD V0, 1; LD I, 0x300; STBCD V0; JMP 0x200
+        let mut vm = vm(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x33, 0x12, 0x00]);
+        let symbols = Symbols::parse("0x204 store").unwrap();
+        let mut script = Script::parse(
+            "let stores = 0;\nlet ones = 0;\n\
+             on_instruction(\"store\", || { stores += 1; });\n\
+             on_write(0x302, |address, value| { if value == 5 { ones += 1; } });\n\
+             on_frame(|| {\n  print(`frame ${frame()} ${v(0)}`);\n\
+             \x20 assert(v(0) < 5, \"too slow\");\n  if frame() == 2 { stop(); }\n});",
+            Some(&symbols),
+        )
+        .unwrap();
+        script.start(&mut vm);
+        for _ in 0..5 {
+            script.run_frame(&mut vm).unwrap();
+        }
+        assert!(script.is_stopped());
+        assert_eq!(script.frame(), 2);
+        assert_eq!(script.variable("stores"), Some(5));
+        assert_eq!(script.variable("ones"), Some(1)); // V0 = 5 stores digit 5 once
+        assert_eq!(script.take_output(), ["frame 1 3", "frame 2 5"]);
+        assert_eq!(
+            script.failures(),
+            ["line 7: assertion failed at frame 2: too slow"]
+        );
+    }
+
+    #[test]
+    fn test_keys_and_errors() {
+        let mut vm = vm(&[0x12, 0x00]);
+        let mut script = Script::parse(
+            "press(0xA);\nlet k = if key(10) { 1 } else { 0 };\nrelease(10);\nv(16);",
+            None,
+        )
+        .unwrap();
+        script.start(&mut vm);
+        assert_eq!(script.variable("k"), Some(1));
+        assert!(!vm.keys()[10]);
+        assert_eq!(script.failures(), ["line 4: no register V16"]);
+        assert!(script.is_stopped());
+
+        for (call, error) in [
+            ("press(16)", "no key 16"),
+            ("release(-1)", "no key -1"),
+            ("key(16)", "no key 16"),
+            ("pixel(64, 0)", "pixel (64, 0) is off the screen"),
+            ("pixel(0, 32)", "pixel (0, 32) is off the screen"),
+        ] {
+            let mut script = Script::parse(call, None).unwrap();
+            script.start(&mut vm);
+            assert_eq!(script.failures(), [format!("line 1: {error}")]);
+        }
+        assert!(!vm.keys()[0]);
+
+        let mut script = Script::parse("on_write(\"nowhere\", |a, v| {});", None).unwrap();
+        script.start(&mut vm);
+        assert_eq!(
+            script.failures(),
+            ["line 1: unknown label or address \"nowhere\""]
+        );
+
+        let mut script = Script::parse("on_frame(|| {\n  mem(0x10000);\n});", None).unwrap();
+        script.start(&mut vm);
+        script.run_frame(&mut vm).unwrap();
+        assert_eq!(
+            script.failures(),
+            ["line 2: address 0x10000 is outside memory"]
+        );
+        assert!(script.is_stopped());
+
+        assert!(Script::parse("if true { print(1)", None).is_err());
+        assert!(Script::parse("x = 1;", None).is_err());
+    }
+}
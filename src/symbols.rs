@@ -0,0 +1,182 @@
+// Labels for addresses in a ROM, so tools can print `main_loop+0x04` instead of
+// `0x0234`. The file is plain text, one label per line:
+//
+//     # breakout.8o
+//     0x200 main
+//     0x234 main_loop
+//     draw_paddle 0x2A0
+//
+// Either order works when the address has a 0x prefix, otherwise the address
+// comes first. Lines like `name = 0x2A0` or `:const name 0x2A0` parse too, as `=`
+// and words starting with `:` are skipped.
+use crate::config::sidecar;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    addresses: BTreeMap<String, u16>,
+}
+
+fn parse_address(field: &str, prefixed: bool) -> Option<u16> {
+    match field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None if !prefixed => u16::from_str_radix(field, 16).ok(),
+        None => None,
+    }
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Self::default();
+        for (n, entry) in text.lines().enumerate() {
+            let entry = entry.split(['#', ';']).next().unwrap_or_default().trim();
+            let fields: Vec<&str> = entry
+                .split_whitespace()
+                .filter(|f| *f != "=" && !f.starts_with(':'))
+                .collect();
+            let symbol = match fields.as_slice() {
+                [] => continue,
+                [a, b] => match (parse_address(a, true), parse_address(b, true)) {
+                    (None, Some(address)) => Some((address, *a)),
+                    _ => parse_address(a, false).map(|address| (address, *b)),
+                },
+                _ => None,
+            };
+            let Some((address, name)) = symbol else {
+                return Err(format!(
+                    "line {}: expected `address name`, got {entry:?}",
+                    n + 1
+                ));
+            };
+            symbols.insert(address, name);
+        }
+        Ok(symbols)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&std::fs::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    // `file` if given, else the ROM's `.sym` sidecar when there is one
+    pub fn for_rom(rom_path: &Path, file: Option<&str>) -> io::Result<Option<Self>> {
+        if let Some(file) = file {
+            return Self::read(file).map(Some);
+        }
+        let sidecar = sidecar(rom_path, "sym");
+        if sidecar.exists() {
+            Self::read(sidecar).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // a later label for the same address replaces the earlier one
+    pub fn insert(&mut self, address: u16, name: &str) {
+        if let Some(old) = self.labels.insert(address, name.to_string()) {
+            self.addresses.remove(&old);
+        }
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // label at exactly this address
+    #[must_use]
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    // closest label at or below `address` and how far past it the address is
+    #[must_use]
+    pub fn nearest(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range(..=address)
+            .next_back()
+            .map(|(&at, name)| (name.as_str(), address - at))
+    }
+
+    // `main_loop`, `main_loop+0x04`, or `0x0234` when no label comes before it
+    #[must_use]
+    pub fn describe(&self, address: u16) -> String {
+        match self.nearest(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{name}+{offset:#04X}"),
+            None => format!("{address:#06X}"),
+        }
+    }
+
+    // Address of `main_loop`, `main_loop+4` (offset in hex) or a plain hex address,
+    // as typed when setting a breakpoint.
+    #[must_use]
+    pub fn resolve(&self, expression: &str) -> Option<u16> {
+        let expression = expression.trim();
+        let (name, offset) = match expression.split_once('+') {
+            Some((name, offset)) => (name.trim(), parse_address(offset.trim(), false)?),
+            None => (expression, 0),
+        };
+        let base = self.address(name).or_else(|| parse_address(name, false))?;
+        base.checked_add(offset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.labels
+            .iter()
+            .map(|(&address, name)| (address, name.as_str()))
+    }
+}
+
+// `0x0234` without symbols, `main_loop+0x04` with
+#[must_use]
+pub fn describe(symbols: Option<&Symbols>, address: u16) -> String {
+    symbols.map_or_else(|| format!("{address:#06X}"), |s| s.describe(address))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Symbols;
+
+    #[test]
+    fn test_parse_symbols() {
+        let symbols = Symbols::parse(
+            "# demo.8o\n0x200 main\n234 main_loop # loop\ndraw 0x2A0\n:const beef = 0x300\n",
+        )
+        .unwrap();
+        assert_eq!(symbols.address("main_loop"), Some(0x234));
+        assert_eq!(symbols.label(0x2A0), Some("draw"));
+        assert_eq!(symbols.label(0x300), Some("beef"));
+        assert!(Symbols::parse("main").is_err());
+        assert!(Symbols::parse("main 200").is_err());
+    }
+
+    #[test]
+    fn test_describe_and_resolve() {
+        let symbols = Symbols::parse("0x200 main\n0x234 main_loop").unwrap();
+        assert_eq!(symbols.describe(0x238), "main_loop+0x04");
+        assert_eq!(symbols.describe(0x200), "main");
+        assert_eq!(symbols.describe(0x100), "0x0100");
+        assert_eq!(symbols.resolve("main_loop"), Some(0x234));
+        assert_eq!(symbols.resolve("main_loop+0A"), Some(0x23E));
+        assert_eq!(symbols.resolve("0x300"), Some(0x300));
+        assert_eq!(symbols.resolve("nowhere"), None);
+    }
+}
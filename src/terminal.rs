@@ -0,0 +1,104 @@
+// ANSI text rendering of the framebuffer, for frontends that run in a terminal
+use crate::{Palette, SCREEN_HEIGHT, SCREEN_WIDTH, VM};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    HalfBlock, // 1x2 pixels per cell, 64x16 cells, two colours per cell
+    Braille,   // 2x4 pixels per cell, 32x8 cells
+}
+
+// braille dot bit for each pixel of a 2x4 cell, indexed [y][x]
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+fn fg([r, g, b]: [u8; 3]) -> String {
+    format!("\x1b[38;2;{r};{g};{b}m")
+}
+
+fn bg([r, g, b]: [u8; 3]) -> String {
+    format!("\x1b[48;2;{r};{g};{b}m")
+}
+
+// Whole frame as text, one line per cell row separated by "\r\n" so it also
+// displays correctly in raw mode. Colour escapes are only emitted when they change.
+#[must_use]
+pub fn render(vm: &VM, glyphs: Glyphs) -> String {
+    match glyphs {
+        Glyphs::HalfBlock => render_half_block(vm, vm.palette()),
+        Glyphs::Braille => render_braille(vm, vm.palette()),
+    }
+}
+
+fn render_half_block(vm: &VM, palette: Palette) -> String {
+    let colors = [
+        crate::vm::to_rgb(palette.background),
+        crate::vm::to_rgb(palette.foreground),
+    ];
+    let mut out = String::new();
+    for row in 0..(SCREEN_HEIGHT / 2) as u8 {
+        let mut current = None;
+        for x in 0..SCREEN_WIDTH as u8 {
+            let top = vm.pixel(x, row * 2);
+            let bottom = vm.pixel(x, row * 2 + 1);
+            if current != Some((top, bottom)) {
+                out.push_str(&fg(colors[usize::from(top)]));
+                out.push_str(&bg(colors[usize::from(bottom)]));
+                current = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+fn render_braille(vm: &VM, palette: Palette) -> String {
+    let mut out = String::new();
+    for row in 0..(SCREEN_HEIGHT / 4) as u8 {
+        let _ = write!(
+            out,
+            "{}{}",
+            fg(crate::vm::to_rgb(palette.foreground)),
+            bg(crate::vm::to_rgb(palette.background))
+        );
+        for col in 0..(SCREEN_WIDTH / 2) as u8 {
+            let mut bits = 0;
+            for (dy, dots) in BRAILLE_DOTS.iter().enumerate() {
+                for (dx, dot) in dots.iter().enumerate() {
+                    if vm.pixel(col * 2 + dx as u8, row * 4 + dy as u8) {
+                        bits |= dot;
+                    }
+                }
+            }
+            out.push(char::from_u32(0x2800 + u32::from(bits)).unwrap_or(' '));
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Glyphs};
+    use crate::VM;
+
+    #[test]
+    fn test_half_block() {
+        let mut vm = VM::new();
+        vm.set_pixel(0, 0);
+        let frame = render(&vm, Glyphs::HalfBlock);
+        assert_eq!(frame.lines().count(), 16);
+        assert!(frame.starts_with("\x1b[38;2;0;227;48m\x1b[48;2;0;0;0m▀\x1b[38;2;0;0;0m"));
+        assert_eq!(frame.matches('▀').count(), 64 * 16);
+    }
+
+    #[test]
+    fn test_braille() {
+        let mut vm = VM::new();
+        vm.set_pixel(0, 0);
+        vm.set_pixel(1, 3);
+        let frame = render(&vm, Glyphs::Braille);
+        assert_eq!(frame.lines().count(), 8);
+        assert!(frame.lines().next().unwrap().contains("⢁⠀"));
+    }
+}
@@ -0,0 +1,430 @@
+// Execution traces, one record per instruction: its address and opcode, then the
+// registers and I after it ran. Traces from other emulators can be compared with
+// ours in lockstep to find the first instruction where the two disagree.
+//
+// CSV has a header naming the columns, in any order, and further columns such as
+// timers or cycle counts are ignored. Values are hex, with or without `0x`:
+//
+//     pc,opcode,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i
+//     0x0200,0x00E0,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,0x0000
+//
+// JSON lines have the same fields, with V0-VF as `v0`..`vf` or an array `v`, and
+// values as numbers or hex strings:
+//
+//     {"pc": 512, "opcode": "0x6A02", "v": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0], "i": 0}
+//
+// With symbols, recorded traces gain a `label` column and divergence reports name
+// the instructions they show.
+use crate::symbols::{describe, Symbols};
+use crate::{Fault, VM};
+use serde_json::Value;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceStep {
+    pub pc: u16, // where the instruction was
+    pub opcode: u16,
+    pub v: [u8; 16], // after it ran
+    pub i: u16,
+}
+
+const CSV_HEADER: &str = "pc,opcode,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i";
+const FIELDS: [&str; 19] = [
+    "pc", "opcode", "v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "va", "vb", "vc",
+    "vd", "ve", "vf", "i",
+];
+
+fn parse_hex(field: &str) -> Option<u16> {
+    let field = field.trim().trim_matches('"');
+    let hex = field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+        .unwrap_or(field);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn json_number(value: &Value) -> Option<u16> {
+    match value {
+        Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+        Value::String(s) => parse_hex(s),
+        _ => None,
+    }
+}
+
+impl TraceStep {
+    // from the 19 fields in `FIELDS` order
+    fn from_fields(fields: [u16; 19]) -> Option<Self> {
+        let mut v = [0; 16];
+        for (r, &value) in v.iter_mut().zip(&fields[2..18]) {
+            *r = u8::try_from(value).ok()?;
+        }
+        Some(Self {
+            pc: fields[0],
+            opcode: fields[1],
+            v,
+            i: fields[18],
+        })
+    }
+
+    fn from_json(line: &str) -> Result<Self, String> {
+        let record: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let mut fields = [0; 19];
+        for (n, name) in FIELDS.iter().enumerate() {
+            let value = match (&record[*name], &record["v"]) {
+                (Value::Null, Value::Array(v)) if (2..18).contains(&n) => {
+                    v.get(n - 2).unwrap_or(&Value::Null)
+                }
+                (value, _) => value,
+            };
+            fields[n] = json_number(value).ok_or_else(|| format!("bad or missing `{name}`"))?;
+        }
+        Self::from_fields(fields).ok_or_else(|| "register out of range".to_string())
+    }
+}
+
+// Reads a CSV or, when the first line starts with `{`, JSON lines trace
+pub fn parse(text: &str) -> Result<Vec<TraceStep>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, first)) = lines.next() else {
+        return Ok(vec![]);
+    };
+    if first.trim_start().starts_with('{') {
+        return std::iter::once((0, first))
+            .chain(lines)
+            .map(|(n, line)| TraceStep::from_json(line).map_err(|e| format!("line {}: {e}", n + 1)))
+            .collect();
+    }
+
+    let header: Vec<String> = first.split(',').map(|c| c.trim().to_lowercase()).collect();
+    let mut columns = [0; 19];
+    for (column, name) in columns.iter_mut().zip(FIELDS) {
+        *column = header
+            .iter()
+            .position(|c| c == name || (name == "opcode" && c == "op"))
+            .ok_or_else(|| format!("line 1: no `{name}` column"))?;
+    }
+    lines
+        .map(|(n, line)| {
+            let cells: Vec<&str> = line.split(',').collect();
+            let mut fields = [0; 19];
+            for (field, &column) in fields.iter_mut().zip(&columns) {
+                *field = cells
+                    .get(column)
+                    .and_then(|c| parse_hex(c))
+                    .ok_or_else(|| {
+                        format!("line {}: bad or missing `{}`", n + 1, header[column])
+                    })?;
+            }
+            TraceStep::from_fields(fields)
+                .ok_or_else(|| format!("line {}: register out of range", n + 1))
+        })
+        .collect()
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<TraceStep>> {
+    let path = path.as_ref();
+    parse(&std::fs::read_to_string(path)?).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
+}
+
+// with a `label` column for each instruction's address when there are symbols
+pub fn write_csv<W: Write>(
+    mut writer: W,
+    steps: &[TraceStep],
+    symbols: Option<&Symbols>,
+) -> io::Result<()> {
+    let label = if symbols.is_some() { ",label" } else { "" };
+    writeln!(writer, "{CSV_HEADER}{label}")?;
+    for step in steps {
+        write!(writer, "{:#06X},{:#06X}", step.pc, step.opcode)?;
+        for v in step.v {
+            write!(writer, ",{v:02X}")?;
+        }
+        write!(writer, ",{:#06X}", step.i)?;
+        if let Some(symbols) = symbols {
+            write!(writer, ",{}", symbols.describe(step.pc))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// Runs `vm` frame by frame, so timers tick as they would in play, handing `each`
+// every instruction's record until it returns false. A fault leaves PC on the
+// faulting instruction.
+pub fn run(vm: &mut VM, mut each: impl FnMut(&VM, TraceStep) -> bool) -> Result<(), Fault> {
+    let mut pending = None; // address and opcode of the instruction that just ran
+    let mut done = false;
+    while !done {
+        vm.run_frame_until(|vm| {
+            if let Some((pc, opcode)) = pending.take() {
+                let step = TraceStep {
+                    pc,
+                    opcode,
+                    v: *vm.registers(),
+                    i: vm.index(),
+                };
+                if !each(vm, step) {
+                    done = true;
+                    return true;
+                }
+            }
+            let pc = vm.program_counter();
+            let bytes = vm.read_memory(pc, 2);
+            let opcode = u16::from(bytes.first().copied().unwrap_or(0)) << 8
+                | u16::from(bytes.get(1).copied().unwrap_or(0));
+            pending = Some((pc, opcode));
+            false
+        })?;
+    }
+    Ok(())
+}
+
+// our own trace of the next `steps` instructions
+pub fn record(vm: &mut VM, steps: usize) -> Result<Vec<TraceStep>, (Vec<TraceStep>, Fault)> {
+    let mut trace = Vec::with_capacity(steps);
+    if steps == 0 {
+        return Ok(trace);
+    }
+    match run(vm, |_, step| {
+        trace.push(step);
+        trace.len() < steps
+    }) {
+        Ok(()) => Ok(trace),
+        Err(fault) => Err((trace, fault)),
+    }
+}
+
+// where a run first disagreed with a reference trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize, // index into the reference trace
+    pub expected: TraceStep,
+    pub actual: Option<TraceStep>, // None when the instruction faulted
+    pub fault: Option<Fault>,
+    pub previous: Option<TraceStep>, // last step both agreed on
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+// Runs `vm` against `expected` until they disagree, returning the steps that
+// matched when the whole trace did.
+pub fn compare(vm: &mut VM, expected: &[TraceStep]) -> Result<usize, Box<Divergence>> {
+    let mut matched = 0;
+    let mut divergence = None;
+    let result = if expected.is_empty() {
+        Ok(())
+    } else {
+        run(vm, |vm, actual| {
+            let want = expected[matched];
+            if actual != want {
+                divergence = Some(Divergence {
+                    step: matched,
+                    expected: want,
+                    actual: Some(actual),
+                    fault: None,
+                    previous: matched.checked_sub(1).map(|n| expected[n]),
+                    stack: vm.stack().to_vec(),
+                    delay_timer: vm.delay_timer(),
+                    sound_timer: vm.sound_timer(),
+                });
+                return false;
+            }
+            matched += 1;
+            matched < expected.len()
+        })
+    };
+    if let Err(fault) = result {
+        divergence = Some(Divergence {
+            step: matched,
+            expected: expected[matched],
+            actual: None,
+            fault: Some(fault),
+            previous: matched.checked_sub(1).map(|n| expected[n]),
+            stack: vm.stack().to_vec(),
+            delay_timer: vm.delay_timer(),
+            sound_timer: vm.sound_timer(),
+        });
+    }
+    divergence.map_or(Ok(matched), |d| Err(Box::new(d)))
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}  {:04X} ", self.pc, self.opcode)?;
+        for v in self.v {
+            write!(f, " {v:02X}")?;
+        }
+        write!(f, "  {:04X}", self.i)
+    }
+}
+
+// `^` under every column of `actual` that differs from `expected`
+fn markers(expected: &TraceStep, actual: &TraceStep) -> String {
+    let mark = |differs: bool, width: usize| if differs { "^" } else { " " }.repeat(width);
+    let mut line = format!(
+        "{}  {} ",
+        mark(expected.pc != actual.pc, 4),
+        mark(expected.opcode != actual.opcode, 4)
+    );
+    for (e, a) in expected.v.iter().zip(&actual.v) {
+        line += " ";
+        line += &mark(e != a, 2);
+    }
+    line += "  ";
+    line += &mark(expected.i != actual.i, 4);
+    line.trim_end().to_string()
+}
+
+impl Divergence {
+    // The rows of the reference and actual state, with the differences marked:
+    //
+    //     step 7 diverged
+    //               PC    OP    V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF  I
+    //     previous  0202  6A02  01 00 00 00 00 00 00 00 00 00 02 00 00 00 00 00  0300
+    //     expected  0204  7A01  01 00 00 00 00 00 00 00 00 00 09 00 00 00 00 00  0300
+    //     actual    0204  7A01  01 00 00 00 00 00 00 00 00 00 03 00 00 00 00 00  0300
+    //                                                         ^^
+    //     stack []  DT 00  ST 00
+    //
+    // With symbols each row ends with the instruction's label, and the stack shows
+    // labels too.
+    #[must_use]
+    pub fn report(&self, symbols: Option<&Symbols>) -> String {
+        let label = |pc: u16| match symbols {
+            Some(symbols) => format!("  {}", symbols.describe(pc)),
+            None => String::new(),
+        };
+        let mut out = format!("step {} diverged\n", self.step);
+        out += "          PC    OP    V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF  I\n";
+        if let Some(previous) = &self.previous {
+            let _ = writeln!(out, "previous  {previous}{}", label(previous.pc));
+        }
+        let _ = writeln!(
+            out,
+            "expected  {}{}",
+            self.expected,
+            label(self.expected.pc)
+        );
+        match (&self.actual, self.fault) {
+            (Some(actual), _) => {
+                let _ = writeln!(out, "actual    {actual}{}", label(actual.pc));
+                let _ = writeln!(out, "          {}", markers(&self.expected, actual));
+            }
+            (None, Some(fault)) => {
+                let _ = writeln!(out, "actual    fault: {fault}");
+            }
+            (None, None) => {}
+        }
+        let stack: Vec<String> = match symbols {
+            Some(_) => self.stack.iter().map(|&a| describe(symbols, a)).collect(),
+            None => self.stack.iter().map(|a| format!("{a:04X}")).collect(),
+        };
+        let _ = write!(
+            out,
+            "stack [{}]  DT {:02X}  ST {:02X}",
+            stack.join(" "),
+            self.delay_timer,
+            self.sound_timer
+        );
+        out
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.report(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare, parse, record, write_csv};
+    use crate::symbols::Symbols;
+    use crate::{Fault, VM};
+
+    // V0 = 1, VA = 2, VA += 1, I = 0x300, jump to the start
+    const ROM: [u8; 10] = [0x60, 0x01, 0x6A, 0x02, 0x7A, 0x01, 0xA3, 0x00, 0x12, 0x00];
+
+    fn vm() -> VM {
+        let mut vm = VM::new();
+        vm.load_bytes(&ROM, 0x200).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_record_and_parse() {
+        let trace = record(&mut vm(), 12).unwrap();
+        assert_eq!(trace.len(), 12);
+        assert_eq!(
+            (trace[2].pc, trace[2].opcode, trace[2].v[10]),
+            (0x204, 0x7A01, 3)
+        );
+        assert_eq!(trace[3].i, 0x300);
+        assert_eq!(trace[5].pc, 0x200);
+
+        let mut out = vec![];
+        write_csv(&mut out, &trace, None).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.starts_with("pc,opcode,v0,"));
+        assert_eq!(parse(&csv).unwrap(), trace);
+
+        let symbols = Symbols::parse("0x200 start\n0x206 point").unwrap();
+        let mut out = vec![];
+        write_csv(&mut out, &trace, Some(&symbols)).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.lines().next().unwrap().ends_with(",i,label"));
+        assert!(csv.lines().nth(3).unwrap().ends_with(",0x0000,start+0x04"));
+        assert!(csv.lines().nth(4).unwrap().ends_with(",0x0300,point"));
+        assert_eq!(parse(&csv).unwrap(), trace);
+
+        let other = "PC,Op,I,V0,V1,V2,V3,V4,V5,V6,V7,V8,V9,VA,VB,VC,VD,VE,VF,cycles\n\
+                     200,6001,0,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,12\n";
+        assert_eq!(parse(other).unwrap(), trace[..1]);
+        let json = "{\"pc\": 512, \"opcode\": \"0x6001\", \"v\": [1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0], \"i\": 0}\n\
+                    {\"pc\": 514, \"opcode\": 27138, \"v0\": 1, \"v1\": 0}";
+        assert_eq!(parse(json).unwrap_err(), "line 2: bad or missing `v2`");
+        assert_eq!(parse(json.lines().next().unwrap()).unwrap(), trace[..1]);
+        let short = "{\"pc\": 512, \"opcode\": 1, \"v\": [1, 2], \"i\": 0}";
+        assert_eq!(parse(short).unwrap_err(), "line 1: bad or missing `v2`");
+        assert!(parse("pc,opcode\n").unwrap_err().contains("no `v0` column"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut trace = record(&mut vm(), 30).unwrap();
+        assert_eq!(compare(&mut vm(), &trace), Ok(30));
+
+        trace[7].v[10] = 9;
+        let divergence = compare(&mut vm(), &trace).unwrap_err();
+        assert_eq!(divergence.step, 7);
+        assert_eq!(divergence.actual.unwrap().v[10], 3);
+        let report = divergence.to_string();
+        assert!(report.starts_with("step 7 diverged\n"), "{report}");
+        assert!(report.contains("\nexpected  0204  7A01  01 00 00 00 00 00 00 00 00 00 09"));
+        assert!(report.contains(&format!("\n{}^^\n", " ".repeat(52))));
+        let symbols = Symbols::parse("0x200 start").unwrap();
+        let report = divergence.report(Some(&symbols));
+        assert!(report.contains(" 09 00 00 00 00 00  0300  start+0x04\nactual "));
+        assert!(report.contains(&format!("\n{}^^\n", " ".repeat(52))));
+
+        // a RET with nothing to return to
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x00, 0xEE], 0x200).unwrap();
+        let divergence = compare(&mut vm, &trace).unwrap_err();
+        assert_eq!(divergence.fault, Some(Fault::StackUnderflow));
+        assert!(divergence
+            .to_string()
+            .contains("actual    fault: return with an empty stack"));
+    }
+}
@@ -0,0 +1,245 @@
+// Every memory access by an instruction goes through the bus, which decides what
+// addresses past the end of RAM mean.
+use super::{Coverage, Fault};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryMode {
+    Wrap4K,  // 4 KiB, addresses wrap around like the mirrored VIP memory map
+    Wrap64K, // 64 KiB as on XO-CHIP, addresses wrap at 16 bits
+    Strict,  // 4 KiB, touching anything past the end is a fault
+}
+
+impl MemoryMode {
+    #[must_use]
+    pub fn size(self) -> usize {
+        match self {
+            MemoryMode::Wrap4K | MemoryMode::Strict => 0x1000,
+            MemoryMode::Wrap64K => 0x10000,
+        }
+    }
+}
+
+impl std::str::FromStr for MemoryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "wrap4k" | "4k" => Ok(MemoryMode::Wrap4K),
+            "wrap64k" | "64k" => Ok(MemoryMode::Wrap64K),
+            "strict" | "fault" => Ok(MemoryMode::Strict),
+            _ => Err(format!(
+                "unknown memory mode {s}, expected wrap4k, wrap64k or strict"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bus {
+    ram: Vec<u8>,
+    mode: MemoryMode,
+    coverage: Option<Box<Coverage>>,
+    writes: Option<Vec<(u16, u8)>>, // address and value of each write, when watched
+}
+
+impl Bus {
+    #[must_use]
+    pub fn new(mode: MemoryMode) -> Self {
+        Self {
+            ram: vec![0; mode.size()],
+            mode,
+            coverage: None,
+            writes: None,
+        }
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> MemoryMode {
+        self.mode
+    }
+
+    // switches mode, keeping whatever still fits
+    pub fn set_mode(&mut self, mode: MemoryMode) {
+        self.ram.resize(mode.size(), 0);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.resize(mode.size());
+        }
+        self.mode = mode;
+    }
+
+    // addresses are u32 so that I + offset can run past 0xFFFF without overflowing
+    fn resolve(&self, address: u32) -> Result<usize, Fault> {
+        let address = address as usize;
+        match self.mode {
+            MemoryMode::Strict if address >= self.ram.len() => Err(Fault::Address(address as u32)),
+            _ => Ok(address % self.ram.len()),
+        }
+    }
+
+    // Faults unless all `len` bytes from `address` can be accessed, so that an
+    // instruction touching several can check before it changes any of them.
+    pub fn check(&self, address: u32, len: u32) -> Result<(), Fault> {
+        (address..address + len).try_for_each(|a| self.resolve(a).map(drop))
+    }
+
+    // the two bytes of the instruction at `pc`
+    pub fn fetch(&mut self, pc: u32) -> Result<(u8, u8), Fault> {
+        let (hi, lo) = (self.resolve(pc)?, self.resolve(pc + 1)?);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_fetch(hi);
+        }
+        Ok((self.ram[hi], self.ram[lo]))
+    }
+
+    // data read by an instruction
+    pub fn read(&mut self, address: u32) -> Result<u8, Fault> {
+        let address = self.resolve(address)?;
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_read(address);
+        }
+        Ok(self.ram[address])
+    }
+
+    pub fn write(&mut self, address: u32, value: u8) -> Result<(), Fault> {
+        let address = self.resolve(address)?;
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_write(address);
+        }
+        if let Some(writes) = self.writes.as_mut() {
+            writes.push((address as u16, value));
+        }
+        self.ram[address] = value;
+        Ok(())
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Box::new(Coverage::new(self.ram.len())));
+    }
+
+    #[must_use]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take().map(|c| *c)
+    }
+
+    pub fn watch_writes(&mut self, watch: bool) {
+        self.writes = watch.then(Vec::new);
+    }
+
+    pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        self.writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl super::VM {
+    pub fn set_memory_mode(&mut self, mode: MemoryMode) {
+        self.memory.set_mode(mode);
+    }
+
+    #[must_use]
+    pub fn memory_mode(&self) -> MemoryMode {
+        self.memory.mode()
+    }
+
+    // Starts or stops logging writes made by instructions (not `write_memory`),
+    // for hooks that react to a ROM changing memory.
+    pub fn watch_writes(&mut self, watch: bool) {
+        self.memory.watch_writes(watch);
+    }
+
+    // address and value of every write since the last call, oldest first
+    pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        self.memory.take_writes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bus, MemoryMode};
+    use crate::vm::Fault;
+    use crate::VM;
+
+    #[test]
+    fn test_wrap_modes() {
+        let mut bus = Bus::new(MemoryMode::Wrap4K);
+        bus.write(0x1001, 7).unwrap();
+        assert_eq!(bus.read(0x0001), Ok(7));
+
+        bus.set_mode(MemoryMode::Wrap64K);
+        assert_eq!(bus.read(0x0001), Ok(7));
+        bus.write(0x1_0002, 9).unwrap();
+        assert_eq!(bus.read(0x0002), Ok(9));
+        assert_eq!(bus.as_slice().len(), 0x10000);
+    }
+
+    #[test]
+    fn test_strict_mode_faults() {
+        let mut bus = Bus::new(MemoryMode::Strict);
+        assert_eq!(bus.write(0xFFF, 1), Ok(()));
+        assert_eq!(bus.read(0x1000), Err(Fault::Address(0x1000)));
+    }
+
+    #[test]
+    fn test_opcodes_near_end_of_memory() {
+        let mut vm = VM::new();
+        vm.set_index(0xFFFF);
+        vm.set_register(0, 1);
+        vm.add_i(0);
+        assert_eq!(vm.index(), 0);
+
+        vm.set_index(0xFFE);
+        vm.set_register(0, 123);
+        vm.store_bcd(0).unwrap();
+        assert_eq!(vm.read_memory(0xFFE, 2), &[1, 2]);
+        assert_eq!(vm.read_memory(0, 1), &[3]);
+
+        vm.set_memory_mode(MemoryMode::Strict);
+        assert_eq!(vm.store_registers(3), Err(Fault::Address(0x1000)));
+        assert_eq!(vm.draw(0, 0, 4), Err(Fault::Address(0x1000)));
+    }
+
+    #[test]
+    fn test_strict_faults_write_nothing() {
+        let mut vm = VM::new();
+        vm.set_memory_mode(MemoryMode::Strict);
+        vm.write_memory(0xFFE, &[0xAA, 0xBB]);
+        vm.set_index(0xFFE);
+        vm.set_register(0, 123);
+        vm.set_register(1, 45);
+        assert_eq!(vm.store_bcd(0), Err(Fault::Address(0x1000)));
+        assert_eq!(vm.store_registers(2), Err(Fault::Address(0x1000)));
+        assert_eq!(vm.read_memory(0xFFE, 2), &[0xAA, 0xBB]);
+        assert_eq!(vm.read_registers(2), Err(Fault::Address(0x1000)));
+        assert_eq!((vm.register(0), vm.register(1)), (123, 45));
+        assert_eq!(vm.index(), 0xFFE);
+        assert_eq!(vm.draw(0, 0, 4), Err(Fault::Address(0x1000)));
+        assert!(vm.framebuffer().rows().iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn test_watch_writes() {
+        let mut vm = VM::new();
+        vm.set_index(0x300);
+        vm.set_register(0, 254);
+        vm.store_bcd(0).unwrap();
+        assert!(vm.take_writes().is_empty());
+
+        vm.watch_writes(true);
+        vm.store_bcd(0).unwrap();
+        vm.write_memory(0x310, &[1]);
+        assert_eq!(vm.take_writes(), [(0x300, 2), (0x301, 5), (0x302, 4)]);
+        assert!(vm.take_writes().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use super::rgba::scale_rgba;
+use super::screen::to_rgb as rgb;
+use crate::{Palette, SCREEN_HEIGHT, SCREEN_WIDTH, VM};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// scale used by the screenshot and recording hotkeys
+pub const CAPTURE_SCALE: u32 = 8;
+// a 4096x2048 image, and well inside the GIF format's 16-bit dimensions
+const MAX_CAPTURE_SCALE: u32 = 64;
+
+// `scale`, at least 1, or an error when it's past `MAX_CAPTURE_SCALE`
+fn check_scale(scale: u32) -> io::Result<u32> {
+    if scale > MAX_CAPTURE_SCALE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("capture scale {scale} is larger than {MAX_CAPTURE_SCALE}"),
+        ));
+    }
+    Ok(scale.max(1))
+}
+
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl VM {
+    pub fn write_screenshot<W: Write>(&self, writer: W, scale: u32) -> io::Result<()> {
+        let scale = check_scale(scale)?;
+        let mut encoder = png::Encoder::new(writer, SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&scale_rgba(&self.rgba_frame(), scale))?;
+        Ok(())
+    }
+
+    pub fn save_screenshot(&self, path: impl AsRef<Path>, scale: u32) -> io::Result<()> {
+        check_scale(scale)?;
+        let file = BufWriter::new(File::create(path)?);
+        self.write_screenshot(file, scale)
+    }
+
+    // F12 saves a screenshot, F10 starts or stops a GIF recording
+    pub(crate) fn handle_capture_keys(&self, recorder: &mut Option<Recorder>) {
+        use macroquad::input::{is_key_pressed, KeyCode};
+
+        if is_key_pressed(KeyCode::F12) {
+            let path = format!("screenshot-{}.png", timestamp());
+            match self.save_screenshot(&path, CAPTURE_SCALE) {
+                Ok(()) => println!("Saved {path}"),
+                Err(e) => println!("Could not save screenshot: {e}"),
+            }
+        }
+
+        if is_key_pressed(KeyCode::F10) {
+            if let Some(r) = recorder.take() {
+                match r.finish().and_then(|mut w| w.flush()) {
+                    Ok(()) => println!("Recording stopped"),
+                    Err(e) => println!("Could not finish recording: {e}"),
+                }
+            } else {
+                let path = format!("recording-{}.gif", timestamp());
+                match Recorder::create(&path, CAPTURE_SCALE, self.palette) {
+                    Ok(r) => {
+                        println!("Recording to {path}");
+                        *recorder = Some(r);
+                    }
+                    Err(e) => println!("Could not start recording: {e}"),
+                }
+            }
+        }
+    }
+}
+
+// Writes consecutive 60Hz frames as an animated GIF. Identical frames are merged into
+// a single longer frame, so a mostly static game produces a small file.
+pub struct Recorder<W: Write = BufWriter<File>> {
+    encoder: gif::Encoder<W>,
+    scale: u32,
+    colors: [[u8; 3]; 2],
+    pending: Option<Vec<u8>>, // unscaled RGBA
+    ticks: u64,               // frames captured so far
+    emitted_centis: u64,      // GIF time already written, in 1/100 s
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, scale: u32, palette: Palette) -> io::Result<Self> {
+        check_scale(scale)?;
+        Self::new(BufWriter::new(File::create(path)?), scale, palette)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W, scale: u32, palette: Palette) -> io::Result<Self> {
+        let scale = check_scale(scale)?;
+        let colors = [rgb(palette.background), rgb(palette.foreground)];
+        let mut encoder = gif::Encoder::new(
+            writer,
+            size(SCREEN_WIDTH, scale),
+            size(SCREEN_HEIGHT, scale),
+            &colors.concat(),
+        )
+        .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            encoder,
+            scale,
+            colors,
+            pending: None,
+            ticks: 0,
+            emitted_centis: 0,
+        })
+    }
+
+    pub fn capture(&mut self, vm: &VM) -> io::Result<()> {
+        let frame = vm.rgba_frame();
+        if self.pending.as_ref() != Some(&frame) {
+            self.flush()?;
+            self.pending = Some(frame);
+        }
+        self.ticks += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        self.encoder.into_inner()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(rgba) = self.pending.take() else {
+            return Ok(());
+        };
+        // frames only ever contain the two palette colours of the global colour table
+        let pixels: Vec<u8> = scale_rgba(&rgba, self.scale)
+            .chunks_exact(4)
+            .map(|p| u8::from(p[..3] == self.colors[1]))
+            .collect();
+        // derive delays from the running total so 60Hz doesn't drift against 100Hz GIF ticks
+        let end_centis = self.ticks * 100 / 60;
+        let delay = u16::try_from(end_centis - self.emitted_centis).unwrap_or(u16::MAX);
+        self.emitted_centis = end_centis;
+
+        let (width, height) = (
+            size(SCREEN_WIDTH, self.scale),
+            size(SCREEN_HEIGHT, self.scale),
+        );
+        let mut frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+        frame.delay = delay;
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
+
+// a scaled GIF dimension, which `check_scale` keeps within 16 bits
+fn size(n: u32, scale: u32) -> u16 {
+    u16::try_from(n * scale).unwrap_or(u16::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Recorder;
+    use crate::{Palette, VM};
+
+    #[test]
+    fn test_screenshot_png() {
+        let mut vm = VM::new();
+        vm.set_pixel(1, 0);
+        let mut buf = vec![];
+        vm.write_screenshot(&mut buf, 2).unwrap();
+
+        let decoder = png::Decoder::new(buf.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&pixels[4..12], &[0, 0, 0, 255, 0, 227, 48, 255]);
+
+        let err = vm.write_screenshot(&mut vec![], 100_000).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = Recorder::new(vec![], 2000, Palette::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_recorder_merges_identical_frames() {
+        let mut vm = VM::new();
+        let mut recorder = Recorder::new(vec![], 1, Palette::default()).unwrap();
+        recorder.capture(&vm).unwrap();
+        recorder.capture(&vm).unwrap();
+        vm.set_pixel(0, 0);
+        recorder.capture(&vm).unwrap();
+        let gif = recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![3, 2]);
+    }
+}
@@ -0,0 +1,478 @@
+// Cheats that hold memory bytes or registers at a value, reapplied after every
+// frame, and a RAM search for finding the byte worth freezing. Cheat files are
+// plain text, loaded from `~/.config/chip8/cheats/<sha1>.cht` and the ROM's
+// `.cht` sidecar:
+//
+//     # brix.ch8
+//     0x3F5 = 05  infinite lives
+//     V4 = 03     wide paddle
+//     !0x3F0 = 99 maximum score   # starts switched off
+//
+// Addresses and values are hex, with or without `0x`.
+use crate::config::{config_dir, sidecar};
+use crate::database::Rom;
+use crate::VM;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Memory(u16),
+    Register(u8),
+}
+
+impl Target {
+    // `0x3F5`, `3F5` or `V4`
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some(register) = text.strip_prefix(['V', 'v']) {
+            if register.len() == 1 {
+                return u8::from_str_radix(register, 16).ok().map(Target::Register);
+            }
+        }
+        parse_hex(text).map(Target::Memory)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Memory(address) => write!(f, "{address:#06X}"),
+            Target::Register(register) => write!(f, "V{register:X}"),
+        }
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub target: Target,
+    pub value: u8,
+    pub enabled: bool,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let off = if self.enabled { "" } else { "!" };
+        write!(
+            f,
+            "{off}{} = {:02X}  {}",
+            self.target, self.value, self.name
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cheats {
+    list: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(rest) => (false, rest),
+                None => (true, line),
+            };
+            let cheat = line.split_once('=').and_then(|(target, rest)| {
+                let rest = rest.trim();
+                let (value, name) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let target = Target::parse(target)?;
+                let value = u8::try_from(parse_hex(value)?).ok()?;
+                let name = match name.trim() {
+                    "" => target.to_string(),
+                    name => name.to_string(),
+                };
+                Some(Cheat {
+                    name,
+                    target,
+                    value,
+                    enabled,
+                })
+            });
+            let Some(cheat) = cheat else {
+                return Err(format!(
+                    "line {}: expected `address = value name`, got {line:?}",
+                    n + 1
+                ));
+            };
+            cheats.list.push(cheat);
+        }
+        Ok(cheats)
+    }
+
+    // Cheats for `rom` from the user's cheat directory, keyed by the ROM's SHA-1,
+    // then from its `.cht` sidecar
+    pub fn for_rom(rom: &Rom, rom_path: &Path) -> io::Result<Self> {
+        let mut cheats = Self::default();
+        let user = config_dir().map(|d| d.join("cheats").join(format!("{}.cht", rom.sha1)));
+        for path in user.into_iter().chain([sidecar(rom_path, "cht")]) {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let more = Self::parse(&text).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {e}", path.display()),
+                )
+            })?;
+            cheats.list.extend(more.list);
+        }
+        Ok(cheats)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.list.push(cheat);
+    }
+
+    pub fn remove(&mut self, n: usize) -> Option<Cheat> {
+        (n < self.list.len()).then(|| self.list.remove(n))
+    }
+
+    // returns false when there is no cheat `n`
+    pub fn set_enabled(&mut self, n: usize, enabled: bool) -> bool {
+        self.list
+            .get_mut(n)
+            .map(|cheat| cheat.enabled = enabled)
+            .is_some()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> + '_ {
+        self.list.iter()
+    }
+}
+
+impl fmt::Display for Cheats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cheat in &self.list {
+            writeln!(f, "{cheat}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+// Narrows memory down to the bytes that behave like the number being looked for:
+// snapshot, play until it changes, filter, repeat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamSearch {
+    previous: Vec<u8>, // memory when last filtered
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    // every byte of memory is a candidate to begin with
+    #[must_use]
+    pub fn new(vm: &VM) -> Self {
+        let previous = vm.memory().to_vec();
+        let candidates = (0..previous.len())
+            .filter_map(|a| u16::try_from(a).ok())
+            .collect();
+        Self {
+            previous,
+            candidates,
+        }
+    }
+
+    // Keeps candidates passing `filter` against the previous snapshot, then takes
+    // a new one. Candidates no longer in memory, as after a switch to a smaller
+    // memory mode, are dropped.
+    pub fn filter(&mut self, vm: &VM, filter: SearchFilter) {
+        let memory = vm.memory();
+        let previous = &self.previous;
+        self.candidates.retain(|&a| {
+            let a = usize::from(a);
+            let (Some(&old), Some(&new)) = (previous.get(a), memory.get(a)) else {
+                return false;
+            };
+            match filter {
+                SearchFilter::Equal(value) => new == value,
+                SearchFilter::Changed => new != old,
+                SearchFilter::Unchanged => new == old,
+                SearchFilter::Increased => new > old,
+                SearchFilter::Decreased => new < old,
+            }
+        });
+        self.previous = memory.to_vec();
+    }
+
+    #[must_use]
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+impl VM {
+    #[must_use]
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    // writes every enabled cheat's value, run at the end of each frame
+    pub(crate) fn apply_cheats(&mut self) {
+        for cheat in self.cheats.list.iter().filter(|c| c.enabled) {
+            match cheat.target {
+                Target::Memory(address) => {
+                    if let Some(byte) = self.memory.as_mut_slice().get_mut(usize::from(address)) {
+                        *byte = cheat.value;
+                    }
+                }
+                Target::Register(register) => self.reg[usize::from(register & 0xF)] = cheat.value,
+            }
+        }
+    }
+}
+
+// Overlay opened with F9 in the window frontend, pausing the game. Up and down
+// pick a cheat or search result, Enter switches the cheat on or off or freezes the
+// result at its current value, Delete removes a cheat. N starts a RAM search and
+// C, U, I and D keep the bytes that changed, stayed unchanged, increased or
+// decreased since the last key. E keeps those equal to a value typed in hex and
+// ended with Enter, where Enter alone gives up.
+#[derive(Debug, Default)]
+pub(crate) struct CheatMenu {
+    selected: usize,
+    search: Option<RamSearch>,
+    equal: Option<String>, // hex digits typed after E
+}
+
+const MENU_RESULTS: usize = 12; // search results listed
+
+impl CheatMenu {
+    fn results(&self) -> &[u16] {
+        self.search.as_ref().map_or(&[], |s| {
+            &s.candidates()[..s.candidates().len().min(MENU_RESULTS)]
+        })
+    }
+
+    pub(crate) fn handle_keys(&mut self, vm: &mut VM) {
+        use macroquad::input::{get_char_pressed, is_key_pressed, KeyCode};
+
+        if let Some(digits) = self.equal.as_mut() {
+            while let Some(c) = get_char_pressed() {
+                if c.is_ascii_hexdigit() && digits.len() < 2 {
+                    digits.push(c.to_ascii_uppercase());
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                digits.pop();
+            }
+            if is_key_pressed(KeyCode::Enter) {
+                let value = u8::from_str_radix(digits, 16).ok();
+                if let (Some(value), Some(search)) = (value, self.search.as_mut()) {
+                    search.filter(vm, SearchFilter::Equal(value));
+                }
+                self.equal = None;
+            }
+            return;
+        }
+        if self.search.is_some() && is_key_pressed(KeyCode::E) {
+            // the E itself is queued as a character too
+            while get_char_pressed().is_some() {}
+            self.equal = Some(String::new());
+            return;
+        }
+
+        let filter = [
+            (KeyCode::C, SearchFilter::Changed),
+            (KeyCode::U, SearchFilter::Unchanged),
+            (KeyCode::I, SearchFilter::Increased),
+            (KeyCode::D, SearchFilter::Decreased),
+        ]
+        .into_iter()
+        .find(|(key, _)| is_key_pressed(*key));
+        match (filter, &mut self.search) {
+            (Some((_, filter)), Some(search)) => search.filter(vm, filter),
+            _ if is_key_pressed(KeyCode::N) => self.search = Some(RamSearch::new(vm)),
+            _ => {}
+        }
+
+        let rows = vm.cheats.len() + self.results().len();
+        if is_key_pressed(KeyCode::Down) {
+            self.selected += 1;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(rows.saturating_sub(1));
+
+        let n = self.selected;
+        if is_key_pressed(KeyCode::Enter) {
+            if let Some(cheat) = vm.cheats.list.get_mut(n) {
+                cheat.enabled = !cheat.enabled;
+            } else if let Some(&address) = self.results().get(n - vm.cheats.len()) {
+                if let Some(&value) = vm.memory().get(usize::from(address)) {
+                    let target = Target::Memory(address);
+                    vm.cheats.add(Cheat {
+                        name: target.to_string(),
+                        target,
+                        value,
+                        enabled: true,
+                    });
+                }
+            }
+        }
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+            vm.cheats.remove(n);
+        }
+    }
+
+    pub(crate) fn draw(&self, vm: &VM) {
+        use macroquad::prelude::*;
+
+        draw_rectangle(
+            0.0,
+            0.0,
+            screen_width(),
+            screen_height(),
+            Color::new(0.0, 0.0, 0.0, 0.8),
+        );
+        let mut lines = vec![
+            "CHEATS  F9 close  Enter toggle/freeze  Del remove".to_string(),
+            "SEARCH  N new  E equal  C changed  U unchanged  I increased  D decreased".to_string(),
+            String::new(),
+        ];
+        let first = lines.len();
+        lines.extend(vm.cheats.iter().map(ToString::to_string));
+        if let Some(search) = &self.search {
+            lines.push(match &self.equal {
+                Some(digits) => format!(
+                    "{} candidates  equal to: {digits}_",
+                    search.candidates().len()
+                ),
+                None => format!("{} candidates", search.candidates().len()),
+            });
+            lines.extend(
+                self.results()
+                    .iter()
+                    .map(|&a| match vm.memory().get(usize::from(a)) {
+                        Some(value) => format!("{a:#06X} = {value:02X}"),
+                        None => format!("{a:#06X}"),
+                    }),
+            );
+        }
+        let selected = first + self.selected + usize::from(self.selected >= vm.cheats.len());
+        for (n, line) in lines.iter().enumerate() {
+            let y = 30.0 + 22.0 * n as f32;
+            let color = if n == selected { YELLOW } else { WHITE };
+            draw_text(line, 20.0, y, 20.0, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cheats, RamSearch, SearchFilter, Target};
+    use crate::{MemoryMode, Timing, VM};
+
+    #[test]
+    fn test_parse_cheats() {
+        let cheats =
+            Cheats::parse("# demo\n0x3F5 = 05  infinite lives\nV4 = 3\n!300=0x99 # off\n").unwrap();
+        let list: Vec<_> = cheats.iter().collect();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[0].target, Target::Memory(0x3F5));
+        assert_eq!(list[0].name, "infinite lives");
+        assert_eq!((list[1].target, list[1].value), (Target::Register(4), 3));
+        assert_eq!(list[1].name, "V4");
+        assert!(!list[2].enabled);
+        assert_eq!(
+            cheats.to_string(),
+            "0x03F5 = 05  infinite lives\nV4 = 03  V4\n!0x0300 = 99  0x0300\n"
+        );
+        assert_eq!(Cheats::parse(&cheats.to_string()), Ok(cheats));
+        assert!(Cheats::parse("0x3F5 = 100").is_err());
+        assert!(Cheats::parse("lives").is_err());
+    }
+
+    #[test]
+    fn test_freeze_and_search() {
+        // stores V0 at 0x300 then counts it down, like lives going
+        let mut vm = VM::new();
+        vm.set_timing(Timing::Fixed(11));
+        vm.load_bytes(
+            &[
+                0x60, 0x80, 0xA3, 0x00, 0xF0, 0x55, 0x70, 0xFF, 0xD0, 0x01, 0x12, 0x02,
+            ],
+            0x200,
+        )
+        .unwrap();
+        vm.run_frame().unwrap();
+        let mut search = RamSearch::new(&vm);
+        vm.run_frame().unwrap();
+        search.filter(&vm, SearchFilter::Decreased);
+        vm.run_frame().unwrap();
+        search.filter(&vm, SearchFilter::Decreased);
+        search.filter(&vm, SearchFilter::Unchanged);
+        search.filter(&vm, SearchFilter::Equal(vm.memory()[0x300]));
+        assert_eq!(search.candidates(), &[0x300]);
+
+        vm.set_cheats(Cheats::parse("0x300 = 09 lives\nV1 = 7").unwrap());
+        for _ in 0..3 {
+            vm.run_frame().unwrap();
+            assert_eq!(vm.memory()[0x300], 9);
+            assert_eq!(vm.register(1), 7);
+        }
+        vm.cheats_mut().set_enabled(0, false);
+        vm.run_frame().unwrap();
+        assert_ne!(vm.memory()[0x300], 9);
+    }
+
+    #[test]
+    fn test_search_after_memory_shrinks() {
+        let mut vm = VM::new();
+        vm.set_memory_mode(MemoryMode::Wrap64K);
+        let mut search = RamSearch::new(&vm);
+        vm.write_memory(0x300, &[1]);
+        vm.write_memory(0x8000, &[1]);
+        search.filter(&vm, SearchFilter::Changed);
+        assert_eq!(search.candidates(), &[0x300, 0x8000]);
+
+        vm.set_memory_mode(MemoryMode::Wrap4K);
+        search.filter(&vm, SearchFilter::Unchanged);
+        assert_eq!(search.candidates(), &[0x300]);
+    }
+}
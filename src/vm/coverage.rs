@@ -0,0 +1,259 @@
+// Which bytes of memory a ROM used and how: fetched as instructions, read as data
+// (sprites for DXYN, FX65) or written (FX33, FX55). Recorded by the bus, so every
+// access counts whichever opcode made it.
+use crate::linemap::LineMap;
+use crate::symbols::Symbols;
+use crate::{OpCode, VM};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    executed: Vec<u32>, // times an instruction was fetched from this address
+    read: Vec<u32>,
+    written: Vec<u32>,
+}
+
+impl Coverage {
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        Self {
+            executed: vec![0; size],
+            read: vec![0; size],
+            written: vec![0; size],
+        }
+    }
+
+    pub(crate) fn resize(&mut self, size: usize) {
+        self.executed.resize(size, 0);
+        self.read.resize(size, 0);
+        self.written.resize(size, 0);
+    }
+
+    pub(crate) fn record_fetch(&mut self, address: usize) {
+        self.executed[address] = self.executed[address].saturating_add(1);
+    }
+
+    pub(crate) fn record_read(&mut self, address: usize) {
+        self.read[address] = self.read[address].saturating_add(1);
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize) {
+        self.written[address] = self.written[address].saturating_add(1);
+    }
+
+    #[must_use]
+    pub fn executed(&self, address: u16) -> u32 {
+        self.executed
+            .get(usize::from(address))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn read(&self, address: u16) -> u32 {
+        self.read.get(usize::from(address)).copied().unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn written(&self, address: u16) -> u32 {
+        self.written.get(usize::from(address)).copied().unwrap_or(0)
+    }
+
+    // the second byte of an executed instruction, rather than a fetch of its own
+    fn inside_instruction(&self, address: u16) -> bool {
+        address > 0 && self.executed(address - 1) > 0
+    }
+
+    fn flags(&self, address: u16) -> String {
+        let flag = |n: u32, c: char| if n > 0 { c } else { '-' };
+        [
+            flag(self.executed(address), 'X'),
+            flag(self.read(address), 'R'),
+            flag(self.written(address), 'W'),
+        ]
+        .iter()
+        .collect()
+    }
+
+    // Disassembly of `range` with how each byte was used. Instructions that were
+    // executed are decoded, everything else is listed a byte at a time, and labels
+    // from `symbols` head the lines they point at:
+    //
+    //     main_loop:
+    //     0x0200  6A02  XR-      41  SET - 0x6A02
+    //     0x030C  80    -R-      12
+    pub fn write_listing<W: Write>(
+        &self,
+        mut writer: W,
+        memory: &[u8],
+        range: RangeInclusive<u16>,
+        symbols: Option<&Symbols>,
+    ) -> io::Result<()> {
+        writeln!(writer, "ADDRESS BYTES FLAGS   COUNT  INSTRUCTION")?;
+        // counted in u32 so a range ending at 0xFFFF still stops
+        let mut at = u32::from(*range.start());
+        while at <= u32::from(*range.end()) {
+            let address = at as u16;
+            if let Some(label) = symbols.and_then(|s| s.label(address)) {
+                writeln!(writer, "{label}:")?;
+            }
+            let a = usize::from(address);
+            let Some(&byte) = memory.get(a) else { break };
+            let count = self.executed(address);
+            if count > 0 {
+                if let Some(&next) = memory.get(a + 1) {
+                    let op = OpCode::from_bytes((byte, next));
+                    writeln!(
+                        writer,
+                        "{address:#06X}  {byte:02X}{next:02X}  {} {count:>7}  {op}",
+                        self.flags(address),
+                    )?;
+                    at += 2;
+                    continue;
+                }
+            }
+            let count = self.read(address).max(self.written(address));
+            writeln!(
+                writer,
+                "{address:#06X}  {byte:02X}    {} {count:>7}",
+                self.flags(address)
+            )?;
+            at += 1;
+        }
+        Ok(())
+    }
+
+    // lcov tracefile with one DA record per mapped source line, or per address when
+    // there is no line map. A line is hit when its code ran or, for data, was read.
+    pub fn write_lcov<W: Write>(
+        &self,
+        mut writer: W,
+        source: &str,
+        map: Option<&LineMap>,
+        range: RangeInclusive<u16>,
+    ) -> io::Result<()> {
+        let mut lines = std::collections::BTreeMap::<u32, u32>::new();
+        for address in range {
+            if self.inside_instruction(address) {
+                continue;
+            }
+            let line = match map {
+                Some(map) => match map.line(address) {
+                    Some(line) => line,
+                    None => continue,
+                },
+                None => u32::from(address),
+            };
+            let hits = if self.executed(address) > 0 {
+                self.executed(address)
+            } else {
+                self.read(address)
+            };
+            let entry = lines.entry(line).or_default();
+            *entry = (*entry).max(hits);
+        }
+
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{source}")?;
+        for (line, hits) in &lines {
+            writeln!(writer, "DA:{line},{hits}")?;
+        }
+        writeln!(writer, "LF:{}", lines.len())?;
+        writeln!(writer, "LH:{}", lines.values().filter(|&&h| h > 0).count())?;
+        writeln!(writer, "end_of_record")
+    }
+}
+
+impl VM {
+    // starts tracking memory use from the next access, discarding earlier coverage
+    pub fn enable_coverage(&mut self) {
+        self.memory.enable_coverage();
+    }
+
+    #[must_use]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.memory.coverage()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.memory.take_coverage()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::linemap::LineMap;
+    use crate::symbols::Symbols;
+    use crate::VM;
+
+    fn run_demo() -> VM {
+        let mut vm = VM::new();
+        // 0x200 LD 0x20A; DRW V0, V0, 1; STBCD V0; READ V0; JMP 0x208; 0x20A sprite
+        vm.load_bytes(
+            &[
+                0xA2, 0x0A, 0xD0, 0x01, 0xF0, 0x33, 0xF0, 0x65, 0x12, 0x08, 0x80,
+            ],
+            0x200,
+        )
+        .unwrap();
+        vm.enable_coverage();
+        for _ in 0..6 {
+            vm.step().unwrap();
+        }
+        vm
+    }
+
+    #[test]
+    fn test_coverage_kinds() {
+        let vm = run_demo();
+        let c = vm.coverage().unwrap();
+        assert_eq!(c.executed(0x200), 1);
+        assert_eq!(c.executed(0x208), 2);
+        assert_eq!(c.read(0x20A), 2); // sprite, then FX65 of the first BCD digit
+        assert_eq!(c.written(0x20B), 1);
+        assert_eq!(c.executed(0x20A), 0);
+    }
+
+    #[test]
+    fn test_listing() {
+        let vm = run_demo();
+        let mut out = vec![];
+        let c = vm.coverage().unwrap();
+        c.write_listing(&mut out, vm.memory(), 0x200..=0x20B, None)
+            .unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[1], "0x0200  A20A  X--       1  LD - 0xA20A");
+        assert_eq!(lines[6], "0x020A  00    -RW       2");
+        assert_eq!(lines[7], "0x020B  00    --W       1");
+
+        let symbols = Symbols::parse("0x208 done\n0x20A sprite").unwrap();
+        let mut out = vec![];
+        c.write_listing(&mut out, vm.memory(), 0x208..=0x20A, Some(&symbols))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().collect::<Vec<_>>()[1..],
+            [
+                "done:",
+                "0x0208  1208  X--       2  JMP - 0x1208",
+                "sprite:",
+                "0x020A  00    -RW       2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let vm = run_demo();
+        let c = vm.coverage().unwrap();
+        let map = LineMap::parse("0x200 1\n0x202 2\n0x204 3\n0x208 5\n0x20A 7\n0x20C 8").unwrap();
+        let mut out = vec![];
+        c.write_lcov(&mut out, "demo.8o", Some(&map), 0x200..=0x20D)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "TN:\nSF:demo.8o\nDA:1,1\nDA:2,1\nDA:3,1\nDA:5,2\nDA:7,2\nDA:8,0\nLF:6\nLH:5\nend_of_record\n"
+        );
+    }
+}
@@ -1,21 +1,23 @@
+use super::Fault;
 use crate::{OpCode, VM};
 
 impl VM {
-    #[allow(clippy::missing_panics_doc)]
-    pub fn execute_op(&mut self, op: &OpCode) {
+    pub fn execute_op(&mut self, op: &OpCode) -> Result<(), Fault> {
         use OpCode::{
             Unknown, ADD, ADDI, CALL, CLS, DRW, JMP, JP, KPR, LD, LDSPR, LDT, RADD, RAND, READ,
             RET, RLD, RND, ROR, RSE, RSHL, RSHR, RSNE, RSUB, RSUBN, RXOR, SE, SET, SETDT, SETST,
             SKNP, SKP, SNE, STBCD, STORE,
         };
 
+        self.last_op = Some(*op);
+        self.profile_op(*op);
         // let start = std::time::Instant::now();
 
         match op {
             CLS => self.clear_display(),
-            RET => self.return_subroutine(),
+            RET => self.return_subroutine()?,
             JMP(x) => self.jump(*x),
-            CALL(x) => self.call(*x),
+            CALL(x) => self.call(*x)?,
             SE { reg, value } => self.skip_equal(*reg, *value),
             SNE { reg, value } => self.skip_not_equal(*reg, *value),
             RSE { reg_x, reg_y } => self.reg_skip_equal(*reg_x, *reg_y),
@@ -28,13 +30,13 @@ impl VM {
             RXOR { reg_x, reg_y } => self.reg_xor(*reg_x, *reg_y),
             RADD { reg_x, reg_y } => self.reg_add(*reg_x, *reg_y),
             RSUB { reg_x, reg_y } => self.reg_sub(*reg_x, *reg_y),
-            RSHR(reg) => self.reg_shift_right(*reg),
+            RSHR { reg_x, reg_y } => self.reg_shift_right(*reg_x, *reg_y),
             RSUBN { reg_x, reg_y } => self.reg_sub_not_borrow(*reg_x, *reg_y),
-            RSHL(reg) => self.reg_shift_left(*reg),
+            RSHL { reg_x, reg_y } => self.reg_shift_left(*reg_x, *reg_y),
             LD(x) => self.set_index(*x),
             JP(x) => self.jump_location(*x),
             RND { reg, value } => self.random(*reg, *value),
-            DRW { x, y, n } => self.draw(*x, *y, *n),
+            DRW { x, y, n } => self.draw(*x, *y, *n)?,
             SKP(x) => self.skip_if_key(*x),
             SKNP(x) => self.skip_if_no_key(*x),
             LDT(x) => self.load_delay_timer(*x),
@@ -43,15 +45,54 @@ impl VM {
             SETST(x) => self.set_sound_timer(*x),
             ADDI(x) => self.add_i(*x),
             LDSPR(x) => self.load_sprite(*x),
-            STBCD(x) => self.store_bcd(*x),
-            STORE(x) => self.store_registers(*x),
-            READ(x) => self.read_registers(*x),
+            STBCD(x) => self.store_bcd(*x)?,
+            STORE(x) => self.store_registers(*x)?,
+            READ(x) => self.read_registers(*x)?,
 
-            Unknown(x) => panic!("Unknown {x:#06X}"),
+            Unknown(x) => return Err(Fault::UnknownOpcode(*x)),
         }
 
         // let end = std::time::Instant::now();
         // let dif = end - start;
         // println!("{op}: {}", dif.as_micros());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::vm::{Fault, MemoryMode};
+    use crate::{OpCode, Platform, Timing, VM};
+
+    #[test]
+    fn test_unknown_opcode_faults() {
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x80, 0x0F], 0x200).unwrap();
+        assert_eq!(vm.step(), Err(Fault::UnknownOpcode(0x800F)));
+        assert_eq!(vm.program_counter(), 0x200);
+    }
+
+    // every instruction, from a state with awkward values everywhere, must either
+    // execute or fault but never panic
+    #[test]
+    fn test_every_opcode_is_panic_free() {
+        for mode in [MemoryMode::Wrap4K, MemoryMode::Strict] {
+            for word in 0..=u16::MAX {
+                let mut vm = VM::new();
+                vm.set_memory_mode(mode);
+                vm.set_quirks(Platform::Chip8.quirks());
+                vm.set_timing(Timing::Vip);
+                for r in 0..16 {
+                    vm.set_register(r, 0xF0 | r);
+                }
+                vm.set_index(0xFFF);
+                vm.set_program_counter(0xFFE);
+                let [hi, lo] = word.to_be_bytes();
+                let op = OpCode::from_bytes((hi, lo));
+                let _ = vm.execute_op(&op);
+                let _ = vm.step();
+                let _ = vm.run_frame();
+            }
+        }
     }
 }
@@ -0,0 +1,24 @@
+use std::fmt;
+
+// Reasons the machine can't carry on executing a ROM. The host keeps running and
+// can inspect the state that led up to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Address(u32),       // memory access outside the address space in strict mode
+    StackOverflow,      // CALL with all 16 stack entries in use
+    StackUnderflow,     // RET with nothing on the stack
+    UnknownOpcode(u16), // instruction that doesn't decode
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Address(address) => write!(f, "address {address:#06X} is outside memory"),
+            Fault::StackOverflow => write!(f, "stack overflow"),
+            Fault::StackUnderflow => write!(f, "return with an empty stack"),
+            Fault::UnknownOpcode(op) => write!(f, "unknown opcode {op:#06X}"),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
@@ -0,0 +1,112 @@
+use super::framebuffer::Row;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// intensity below which a decaying pixel is treated as fully off
+const CUTOFF: f32 = 1.0 / 255.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    None,
+    Phosphor { decay: f32 }, // fraction of brightness a pixel keeps each frame once switched off
+    Blend,                   // OR of the current and previous frame
+}
+
+impl super::VM {
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.stale_rows = u32::MAX;
+    }
+
+    // Called once per rendered frame, only ever reads `screen`. Rows are recomputed
+    // when the framebuffer changed or they are still fading, and the rows that were
+    // recomputed are returned so renderers can skip unchanged frames.
+    pub fn update_intensity(&mut self) -> u32 {
+        let rows = self.screen.take_unrendered() | std::mem::take(&mut self.stale_rows);
+        for y in 0..SCREEN_HEIGHT as usize {
+            if rows & (1 << y) == 0 {
+                continue;
+            }
+            let row = self.screen.row(y as u8);
+            let previous = self.previous_screen[y];
+            for x in 0..SCREEN_WIDTH {
+                let bit = 1 << (Row::BITS - 1 - x);
+                let lit = row & bit != 0;
+                let i = y * SCREEN_WIDTH as usize + x as usize;
+                self.intensity[i] = match self.filter {
+                    Filter::None => f32::from(u8::from(lit)),
+                    Filter::Phosphor { decay } => {
+                        let faded = self.intensity[i] * decay.clamp(0.0, 1.0);
+                        if lit {
+                            1.0
+                        } else if faded < CUTOFF {
+                            0.0
+                        } else {
+                            self.stale_rows |= 1 << y;
+                            faded
+                        }
+                    }
+                    Filter::Blend => f32::from(u8::from(lit || previous & bit != 0)),
+                };
+            }
+            if self.filter == Filter::Blend && row != previous {
+                // pixels only in the previous frame drop out next frame
+                self.stale_rows |= 1 << y;
+            }
+            self.previous_screen[y] = row;
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Filter;
+    use crate::VM;
+
+    #[test]
+    fn test_phosphor_decay() {
+        let mut vm = VM::new();
+        vm.set_filter(Filter::Phosphor { decay: 0.5 });
+        vm.set_pixel(3, 0);
+        vm.update_intensity();
+        assert!((vm.intensity[3] - 1.0).abs() < f32::EPSILON);
+
+        vm.set_pixel(3, 0);
+        vm.update_intensity();
+        assert!((vm.intensity[3] - 0.5).abs() < f32::EPSILON);
+        vm.update_intensity();
+        assert!((vm.intensity[3] - 0.25).abs() < f32::EPSILON);
+
+        for _ in 0..8 {
+            vm.update_intensity();
+        }
+        assert!(vm.intensity[3].abs() < f32::EPSILON);
+        assert!(!vm.pixel(3, 0));
+    }
+
+    #[test]
+    fn test_blend_last_two_frames() {
+        let mut vm = VM::new();
+        vm.set_filter(Filter::Blend);
+        vm.set_pixel(0, 1);
+        vm.update_intensity();
+        vm.set_pixel(0, 1);
+        vm.update_intensity();
+        assert!((vm.intensity[64] - 1.0).abs() < f32::EPSILON);
+        vm.update_intensity();
+        assert!(vm.intensity[64].abs() < f32::EPSILON);
+        assert_eq!(vm.update_intensity(), 0);
+    }
+
+    #[test]
+    fn test_only_changed_rows_are_recomputed() {
+        let mut vm = VM::new();
+        vm.update_intensity();
+        assert_eq!(vm.update_intensity(), 0);
+        vm.set_pixel(5, 7);
+        assert_eq!(vm.update_intensity(), 1 << 7);
+        assert_eq!(vm.update_intensity(), 0);
+        vm.set_filter(Filter::Blend);
+        assert_eq!(vm.update_intensity(), u32::MAX);
+    }
+}
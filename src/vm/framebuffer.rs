@@ -0,0 +1,192 @@
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// One bit per pixel, most significant bit is x = 0 so sprite bytes line up
+// unshifted. Wide enough for SCHIP's 128 pixel rows; bits past SCREEN_WIDTH are
+// never lit.
+pub type Row = u128;
+
+// the bits of a row that are on screen
+pub const VISIBLE: Row = Row::MAX << (Row::BITS - SCREEN_WIDTH);
+
+const ROWS: usize = SCREEN_HEIGHT as usize;
+
+// Monochrome display packed one row per integer, with bitmasks of the rows that
+// changed since a frontend, and since the VM's own renderer, last looked at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framebuffer {
+    rows: [Row; ROWS],
+    dirty: u32,      // bit y set when row y changed, for frontends
+    unrendered: u32, // the same for the renderer, so frontends can't take its updates
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            rows: [0; ROWS],
+            dirty: 0,
+            unrendered: 0,
+        }
+    }
+}
+
+impl Framebuffer {
+    #[must_use]
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        let x = u32::from(x) % SCREEN_WIDTH;
+        let y = usize::from(y) % ROWS;
+        self.rows[y] & (1 << (Row::BITS - 1 - x)) != 0
+    }
+
+    #[must_use]
+    pub fn row(&self, y: u8) -> Row {
+        self.rows[usize::from(y) % ROWS]
+    }
+
+    #[must_use]
+    pub fn rows(&self) -> &[Row; ROWS] {
+        &self.rows
+    }
+
+    fn mark(&mut self, y: usize) {
+        self.dirty |= 1 << y;
+        self.unrendered |= 1 << y;
+    }
+
+    // XORs the visible part of `bits` into row `y`, returning true if any lit pixel
+    // was switched off
+    pub fn xor_row(&mut self, y: u8, bits: Row) -> bool {
+        let (y, bits) = (usize::from(y) % ROWS, bits & VISIBLE);
+        let collision = self.rows[y] & bits != 0;
+        self.rows[y] ^= bits;
+        if bits != 0 {
+            self.mark(y);
+        }
+        collision
+    }
+
+    pub fn set_row(&mut self, y: u8, bits: Row) {
+        let (y, bits) = (usize::from(y) % ROWS, bits & VISIBLE);
+        if self.rows[y] != bits {
+            self.rows[y] = bits;
+            self.mark(y);
+        }
+    }
+
+    // one byte per pixel, 0 or 1, row by row: the layout array libraries expect
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.rows
+            .iter()
+            .flat_map(|row| (0..SCREEN_WIDTH).map(move |x| (row >> (Row::BITS - 1 - x) & 1) as u8))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        for y in 0..ROWS as u8 {
+            self.set_row(y, 0);
+        }
+    }
+
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    #[must_use]
+    pub fn dirty_rows(&self) -> u32 {
+        self.dirty
+    }
+
+    // rows changed since the last call, for a frontend to redraw
+    pub fn take_dirty(&mut self) -> u32 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // rows changed since the VM's renderer last looked
+    pub(super) fn take_unrendered(&mut self) -> u32 {
+        std::mem::take(&mut self.unrendered)
+    }
+}
+
+impl super::VM {
+    #[must_use]
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.screen
+    }
+
+    // Rows of the display changed since the last call, used by frontends to skip
+    // redraws. The VM's own renderer keeps a separate record, so this takes nothing
+    // from it.
+    pub fn take_dirty_rows(&mut self) -> u32 {
+        self.screen.take_dirty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Framebuffer, Row};
+    use crate::{SCREEN_WIDTH, VM};
+
+    #[test]
+    fn test_xor_row() {
+        let mut fb = Framebuffer::default();
+        assert!(!fb.xor_row(2, 0xF0 << (Row::BITS - 8)));
+        assert!(fb.pixel(0, 2) && fb.pixel(3, 2) && !fb.pixel(4, 2));
+        assert_eq!(fb.take_dirty(), 1 << 2);
+
+        assert!(fb.xor_row(2, 0x18 << (Row::BITS - 8)));
+        assert!(fb.pixel(4, 2) && !fb.pixel(3, 2));
+        assert!(!fb.xor_row(3, 0));
+        assert_eq!(fb.take_dirty(), 1 << 2);
+        assert!(!fb.is_dirty());
+    }
+
+    #[test]
+    fn test_clear_only_marks_lit_rows() {
+        let mut fb = Framebuffer::default();
+        fb.xor_row(31, 1 << (Row::BITS - SCREEN_WIDTH));
+        fb.take_dirty();
+        fb.clear();
+        assert_eq!(fb.dirty_rows(), 1 << 31);
+        assert!(fb.rows().iter().all(|&r| r == 0));
+    }
+
+    #[test]
+    fn test_draw_collision_is_per_sprite() {
+        let mut vm = VM::new();
+        vm.set_index(0); // font sprite "0"
+        vm.set_register(0, 60);
+        vm.draw(0, 1, 5).unwrap();
+        assert_eq!(vm.reg[0xF], 0);
+        assert!(vm.pixel(63, 0) && !vm.pixel(0, 0) && vm.pixel(60, 4));
+
+        // only the top row overlaps, VF must not be cleared by later rows
+        vm.set_index(5 * 7); // "7"
+        vm.set_register(1, 4);
+        vm.draw(0, 1, 5).unwrap();
+        assert_eq!(vm.reg[0xF], 1);
+    }
+
+    #[test]
+    fn test_sprites_clip_or_wrap_at_the_right_edge() {
+        for wrap in [false, true] {
+            let mut vm = VM::new();
+            vm.quirks.wrap = wrap;
+            vm.set_index(0); // font sprite "0", top row 0xF0
+            vm.set_register(0, 62);
+            vm.draw(0, 1, 1).unwrap();
+            assert!(vm.pixel(62, 0) && vm.pixel(63, 0));
+            assert_eq!(vm.pixel(0, 0) && vm.pixel(1, 0), wrap);
+            assert_eq!(vm.framebuffer().row(0) & !super::VISIBLE, 0);
+        }
+    }
+
+    #[test]
+    fn test_frontends_dont_take_renderer_updates() {
+        let mut vm = VM::new();
+        vm.set_pixel(5, 3);
+        assert_eq!(vm.take_dirty_rows(), 1 << 3);
+        assert_eq!(vm.update_intensity() & 1 << 3, 1 << 3);
+        assert_eq!(vm.take_dirty_rows(), 0);
+    }
+}
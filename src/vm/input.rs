@@ -1,33 +1,91 @@
 use macroquad::prelude::*;
 
-static KEYMAP: [KeyCode; 16] = [
-    KeyCode::X,    // 0
-    KeyCode::Key1, // 1
-    KeyCode::Key2, // 2
-    KeyCode::Key3, // 3
-    KeyCode::Q,    // 4
-    KeyCode::W,    // 5
-    KeyCode::E,    // 6
-    KeyCode::A,    // 7
-    KeyCode::S,    // 8
-    KeyCode::D,    // 9
-    KeyCode::Z,    // A
-    KeyCode::C,    // B
-    KeyCode::Key4, // C
-    KeyCode::R,    // D
-    KeyCode::F,    // E
-    KeyCode::V,    // F
+// default host key for each CHIP-8 key, shared by every frontend
+pub static KEYMAP: [char; 16] = [
+    'x', // 0
+    '1', // 1
+    '2', // 2
+    '3', // 3
+    'q', // 4
+    'w', // 5
+    'e', // 6
+    'a', // 7
+    's', // 8
+    'd', // 9
+    'z', // A
+    'c', // B
+    '4', // C
+    'r', // D
+    'f', // E
+    'v', // F
 ];
 
+fn key_code(c: char) -> Option<KeyCode> {
+    let code = match c.to_ascii_lowercase() {
+        '0' => KeyCode::Key0,
+        '1' => KeyCode::Key1,
+        '2' => KeyCode::Key2,
+        '3' => KeyCode::Key3,
+        '4' => KeyCode::Key4,
+        '5' => KeyCode::Key5,
+        '6' => KeyCode::Key6,
+        '7' => KeyCode::Key7,
+        '8' => KeyCode::Key8,
+        '9' => KeyCode::Key9,
+        'a' => KeyCode::A,
+        'b' => KeyCode::B,
+        'c' => KeyCode::C,
+        'd' => KeyCode::D,
+        'e' => KeyCode::E,
+        'f' => KeyCode::F,
+        'g' => KeyCode::G,
+        'h' => KeyCode::H,
+        'i' => KeyCode::I,
+        'j' => KeyCode::J,
+        'k' => KeyCode::K,
+        'l' => KeyCode::L,
+        'm' => KeyCode::M,
+        'n' => KeyCode::N,
+        'o' => KeyCode::O,
+        'p' => KeyCode::P,
+        'q' => KeyCode::Q,
+        'r' => KeyCode::R,
+        's' => KeyCode::S,
+        't' => KeyCode::T,
+        'u' => KeyCode::U,
+        'v' => KeyCode::V,
+        'w' => KeyCode::W,
+        'x' => KeyCode::X,
+        'y' => KeyCode::Y,
+        'z' => KeyCode::Z,
+        _ => return None,
+    };
+    Some(code)
+}
+
 impl super::VM {
+    pub fn set_keymap(&mut self, keymap: [char; 16]) {
+        self.keymap = keymap.map(|c| c.to_ascii_lowercase());
+    }
+
+    // CHIP-8 key bound to a host key
+    #[must_use]
+    pub fn keypad_index(&self, c: char) -> Option<u8> {
+        let c = c.to_ascii_lowercase();
+        self.keymap.iter().position(|k| *k == c).map(|i| i as u8)
+    }
+
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        self.key[key as usize & 0xF] = down;
+    }
+
     pub fn get_input(&mut self) {
-        for (i, k) in KEYMAP.iter().enumerate() {
-            if is_key_down(*k) {
-                self.key[i] = true;
-                // println!("PRESSED {i}");
-            } else {
-                self.key[i] = false;
-            }
+        for (i, c) in self.keymap.iter().enumerate() {
+            self.key[i] = key_code(*c).is_some_and(is_key_down);
+        }
+
+        if is_key_pressed(KeyCode::F11) {
+            self.toggle_fullscreen();
         }
 
         if is_key_down(KeyCode::Escape) {
@@ -35,3 +93,23 @@ impl super::VM {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{key_code, KEYMAP};
+    use crate::VM;
+
+    #[test]
+    fn test_keymap_round_trip() {
+        let mut vm = VM::new();
+        for (i, c) in KEYMAP.iter().enumerate() {
+            assert_eq!(vm.keypad_index(*c), Some(i as u8));
+            assert!(key_code(*c).is_some());
+        }
+        assert_eq!(vm.keypad_index('Q'), Some(4));
+        assert_eq!(vm.keypad_index('p'), None);
+
+        vm.set_keymap(['P'; 16]);
+        assert_eq!(vm.keypad_index('p'), Some(0));
+    }
+}
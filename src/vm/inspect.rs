@@ -0,0 +1,156 @@
+// Read-only view of the machine state for embedders, debuggers and tests, plus
+// the few setters tooling needs. The display is available through `framebuffer`,
+// and opcode semantics stay behind the operation methods.
+use crate::{OpCode, STACK_SIZE, VM};
+
+impl VM {
+    #[must_use]
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.reg
+    }
+
+    #[must_use]
+    pub fn register(&self, register: u8) -> u8 {
+        self.reg[usize::from(register & 0xF)]
+    }
+
+    #[must_use]
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    #[must_use]
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    // return addresses currently on the stack, oldest first
+    #[must_use]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..(self.stack_pointer + 1) as usize]
+    }
+
+    // index of the top of the stack, -1 when empty
+    #[must_use]
+    pub fn stack_pointer(&self) -> i8 {
+        self.stack_pointer
+    }
+
+    #[must_use]
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    #[must_use]
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.key
+    }
+
+    #[must_use]
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    // up to `len` bytes from `address`, cut short at the end of memory
+    #[must_use]
+    pub fn read_memory(&self, address: u16, len: usize) -> &[u8] {
+        let memory = self.memory.as_slice();
+        let start = usize::from(address).min(memory.len());
+        let end = start.saturating_add(len).min(memory.len());
+        &memory[start..end]
+    }
+
+    // instruction executed by the last step, None before the first
+    #[must_use]
+    pub fn last_op(&self) -> Option<OpCode> {
+        self.last_op
+    }
+
+    // Copies `bytes` into memory at `address`, returning how many fit before the
+    // end of memory.
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> usize {
+        let dest = self
+            .memory
+            .as_mut_slice()
+            .get_mut(usize::from(address)..)
+            .unwrap_or_default();
+        let n = bytes.len().min(dest.len());
+        dest[..n].copy_from_slice(&bytes[..n]);
+        n
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
+
+    // replaces the stack with `entries`, oldest first, keeping at most STACK_SIZE
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn set_stack(&mut self, entries: &[u16]) {
+        let n = entries.len().min(STACK_SIZE);
+        self.stack = [0; STACK_SIZE];
+        self.stack[..n].copy_from_slice(&entries[..n]);
+        self.stack_pointer = n as i8 - 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{OpCode, VM};
+
+    #[test]
+    fn test_inspect_after_step() {
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x63, 0x2A, 0x22, 0x06, 0x00, 0x00, 0xA1, 0x23], 0x200)
+            .unwrap();
+        assert_eq!(vm.last_op(), None);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.register(3), 0x2A);
+        assert_eq!(vm.registers()[3], 0x2A);
+        assert_eq!(vm.program_counter(), 0x206);
+        assert_eq!(vm.stack(), &[0x204]);
+        assert_eq!(vm.stack_pointer(), 0);
+        vm.step().unwrap();
+        assert_eq!(vm.index(), 0x123);
+        assert_eq!(vm.last_op(), Some(OpCode::LD(0x123)));
+    }
+
+    #[test]
+    fn test_memory_access() {
+        let mut vm = VM::new();
+        assert_eq!(vm.write_memory(0x300, &[1, 2, 3]), 3);
+        assert_eq!(vm.read_memory(0x300, 3), &[1, 2, 3]);
+        assert_eq!(vm.write_memory(0xFFE, &[4, 5, 6]), 2);
+        assert_eq!(vm.read_memory(0xFFE, 16), &[4, 5]);
+        assert!(vm.read_memory(0xFFFF, 1).is_empty());
+        assert_eq!(vm.memory().len(), 4096);
+
+        vm.write_memory(0x400, &[0x13, 0x00]); // JMP 0x300
+        vm.set_program_counter(0x400);
+        vm.step().unwrap();
+        assert_eq!(vm.program_counter(), 0x300);
+    }
+
+    #[test]
+    fn test_set_stack() {
+        let mut vm = VM::new();
+        vm.set_stack(&[0x202, 0x30A]);
+        assert_eq!(vm.stack(), &[0x202, 0x30A]);
+        assert_eq!(vm.pop(), Ok(0x30A));
+        vm.set_stack(&[]);
+        assert_eq!(vm.stack_pointer(), -1);
+        vm.set_stack(&[1; 20]);
+        assert_eq!(vm.stack().len(), 16);
+    }
+}
@@ -1,111 +1,255 @@
+use crate::config::{Config, ConfigLayer};
+use crate::database::Rom;
+use crate::watch::{Watch, WatchEvent};
 use macroquad::prelude::*;
+use std::io;
+use std::path::PathBuf;
+mod bus;
+pub use bus::MemoryMode;
+mod fault;
+pub use fault::Fault;
 mod opcodes;
 pub use opcodes::OpCode;
+mod capture;
+pub use capture::Recorder;
+mod cheats;
+pub use cheats::{Cheat, Cheats, RamSearch, SearchFilter, Target};
+mod coverage;
+pub use coverage::Coverage;
 mod execute;
+mod filter;
+mod framebuffer;
+pub use filter::Filter;
+pub use framebuffer::{Framebuffer, Row};
 mod input;
+mod inspect;
+pub use input::KEYMAP;
 mod operations;
+mod profiler;
+pub use profiler::{CallStats, Profiler};
+mod quirks;
+mod random;
+pub use quirks::{Platform, QuirkOverrides, Quirks};
+mod rgba;
+pub use rgba::{scale_rgba, RGBA_LEN};
 mod screen;
+pub(crate) use screen::to_rgb;
+pub use screen::{Palette, Scaling, Viewport};
 mod stack;
 mod timer;
+mod timing;
+pub use timing::Timing;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct VM {
-    memory: [u8; 4096],
+    memory: bus::Bus,
     program_counter: u16,
     i: u16,
     reg: [u8; 16],
     stack: [u16; crate::STACK_SIZE],
     stack_pointer: i8,
     key: [bool; 16],
-    screen: [bool; 64 * 32],
+    screen: Framebuffer,
     delay_timer: u8,
     sound_timer: u8,
     sound_playing: bool,
     key_pressed: Option<u8>,
+    scaling: Scaling,
+    fullscreen: bool,
+    palette: Palette,
+    filter: Filter,
+    intensity: [f32; 64 * 32],
+    previous_screen: [Row; 32],
+    stale_rows: u32,  // rows to recompute next frame whether or not they changed
+    display: Vec<u8>, // filtered RGBA image of the screen
+    texture: Option<Texture2D>, // a GPU handle, shared by clones
+    timing: Timing,
+    cycle_budget: i64,
+    quirks: Quirks,
+    keymap: [char; 16],
+    last_op: Option<OpCode>,
+    profiler: Option<Box<Profiler>>,
+    rng: u64,
+    cheats: Cheats,
+    program_size: usize, // bytes of the ROM loaded by `load_rom`
 }
 
 impl VM {
     #[must_use]
     pub fn new() -> Self {
-        Self::default()
+        Self::from_config(&Config::default())
     }
 
-    pub fn load_program(&mut self, file: &str) -> Result<(), std::io::Error> {
-        use std::io::Read;
-        let mut f = std::fs::File::open(file)?;
-        let mut buf = vec![];
-        f.read_to_end(&mut buf)?;
+    #[must_use]
+    pub fn from_config(config: &Config) -> Self {
+        let mut vm = Self::default();
+        vm.apply_config(config);
+        vm
+    }
 
-        self.load_bytes(&buf, 0x200);
-		self.dump_memory();
-        Ok(())
+    pub fn apply_config(&mut self, config: &Config) {
+        self.set_timing(config.timing);
+        self.set_quirks(config.quirks);
+        self.set_palette(config.palette);
+        self.set_keymap(config.keymap);
+        self.set_scaling(config.scaling);
+        self.set_filter(config.filter);
+        self.set_memory_mode(config.memory);
     }
 
-    #[allow(clippy::missing_panics_doc)]
-    pub fn load_bytes(&mut self, buf: &[u8], offset: u16) {
-        assert!(
-            buf.len() <= 4096,
-            "ROM larger than memory. Tried to read {} bytes",
-            buf.len()
-        );
+    // Loads a ROM file with `patches` and its patch sidecars applied, then the ROM
+    // database entry and any config files for it
+    pub fn load_program(&mut self, file: &str, patches: &[PathBuf]) -> io::Result<Rom> {
+        let rom = crate::patch::load(file.as_ref(), patches)?;
+        let config = Config::resolve(&rom, file.as_ref(), &ConfigLayer::default())?;
+        self.apply_config(&config);
 
-        for (i, b) in buf.iter().enumerate() {
-            self.memory[offset as usize + i] = *b;
+        self.load_rom(&rom)?;
+        Ok(rom)
+    }
+
+    pub fn load_rom(&mut self, rom: &Rom) -> io::Result<()> {
+        self.load_bytes(&rom.bytes, 0x200)?;
+        self.program_size = rom.bytes.len();
+        Ok(())
+    }
+
+    // Swaps in a rebuilt `rom`. A reset starts it afresh under `config`. Otherwise
+    // only the program area is replaced: registers, stack, timers, the screen and
+    // the rest of memory are kept, and execution carries on from PC. A ROM that
+    // doesn't fit leaves the VM as it was.
+    pub fn reload(&mut self, config: &Config, rom: &Rom, keep_state: bool) -> io::Result<()> {
+        if keep_state {
+            fits(rom.bytes.len(), 0x200, config.memory.size())?;
+            self.apply_config(config);
+            let memory = self.memory.as_mut_slice();
+            let end = (0x200 + self.program_size.max(rom.bytes.len())).min(memory.len());
+            memory[0x200.min(end)..end].fill(0);
+            return self.load_rom(rom);
         }
+        let mut fresh = VM::from_config(config);
+        fresh.load_rom(rom)?;
+        // the window and what the player set up outlive the program
+        fresh.texture = self.texture;
+        fresh.fullscreen = self.fullscreen;
+        fresh.sound_playing = self.sound_playing;
+        fresh.cheats = std::mem::take(&mut self.cheats);
+        fresh.profiler = self.profiler.take().map(|_| Box::default());
+        *self = fresh;
+        Ok(())
+    }
+
+    pub fn load_bytes(&mut self, buf: &[u8], offset: u16) -> io::Result<()> {
+        let memory = self.memory.as_mut_slice();
+        fits(buf.len(), offset, memory.len())?;
+
+        memory[offset as usize..offset as usize + buf.len()].copy_from_slice(buf);
+        Ok(())
     }
 
-    pub fn get_instruction(&mut self) -> (u8, u8) {
-        self.program_counter += 2;
-        (
-            self.memory[self.program_counter as usize - 2],
-            self.memory[self.program_counter as usize - 1],
-        )
+    // reads the instruction at PC, then advances PC past it
+    pub fn get_instruction(&mut self) -> Result<(u8, u8), Fault> {
+        let pc = u32::from(self.program_counter);
+        let instruction = self.memory.fetch(pc)?;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(instruction)
     }
 
-    pub async fn run(&mut self) {
-        const CPU_TICK_NANOS: u128 = 1_000_000_000 / 500_000_000; // 500 MHz
-        const UPDATE_TIMESTEP_MICROS: u128 = 1_000_000 / 60; // 60Hz
+    // fetch, decode and execute a single instruction
+    pub fn step(&mut self) -> Result<OpCode, Fault> {
+        let pc = self.program_counter;
+        let op = OpCode::from_bytes(self.get_instruction()?);
+        if let Err(fault) = self.execute_op(&op) {
+            self.program_counter = pc;
+            return Err(fault);
+        }
+        Ok(op)
+    }
 
-        let mut start_tick = std::time::Instant::now();
-        let mut start_timestep = std::time::Instant::now();
+    // runs the window frontend, reloading the ROM as `watch` sees it change
+    pub async fn run(&mut self, mut watch: Option<Watch>) {
+        const FRAME: std::time::Duration = std::time::Duration::from_micros(1_000_000 / 60); // 60Hz
+        const MAX_LAG: std::time::Duration = std::time::Duration::from_millis(100);
+        const WATCH_POLL: std::time::Duration = std::time::Duration::from_millis(250);
+        const NOTICE: std::time::Duration = std::time::Duration::from_secs(3);
+
+        let mut last = std::time::Instant::now();
+        let mut lag = std::time::Duration::ZERO;
+        let mut recorder = None;
+        let mut fault = None;
+        let mut cheat_menu: Option<cheats::CheatMenu> = None;
+        let mut last_poll = std::time::Instant::now();
+        let mut notice: Option<(String, std::time::Instant)> = None;
 
         loop {
-            let end_tick = std::time::Instant::now();
-            let dif_tick = (end_tick - start_tick).as_micros();
-
-            if dif_tick > CPU_TICK_NANOS {
-                // should run 500 MHz
-                let instruction = self.get_instruction(); // get instruction and increments IP by 2
-                let op = OpCode::from_bytes(instruction);
-                self.execute_op(&op);
-                start_tick = std::time::Instant::now();
-            }
+            let now = std::time::Instant::now();
+            lag = (lag + (now - last)).min(MAX_LAG);
+            last = now;
 
-            let end_timestep = std::time::Instant::now();
-            let dif_timestep = (end_timestep - start_timestep).as_micros();
-            if dif_timestep > UPDATE_TIMESTEP_MICROS {
-                // should run at 60 Hz
-                self.get_input();
-                self.draw_screen();
-                self.run_timers();
-                let fps = get_fps();
-                draw_text(&format!("FPS: {fps}"), 80.0, 20.0, 20.0, WHITE);
-                macroquad::prelude::next_frame().await;
-                start_timestep = std::time::Instant::now();
+            self.get_input();
+            self.handle_capture_keys(&mut recorder);
+            if let Some(watch) = watch.as_mut().filter(|_| last_poll.elapsed() >= WATCH_POLL) {
+                last_poll = now;
+                if let Some(event) = watch.poll(self) {
+                    println!("{event}");
+                    if let WatchEvent::Reloaded(_) = event {
+                        fault = None;
+                    }
+                    notice = Some((event.to_string(), now));
+                }
+            }
+            if is_key_pressed(KeyCode::F9) {
+                cheat_menu = match cheat_menu {
+                    Some(_) => None,
+                    None => Some(cheats::CheatMenu::default()),
+                };
+            }
+            if let Some(menu) = cheat_menu.as_mut() {
+                // the game is paused while the menu is open
+                menu.handle_keys(self);
+                lag = std::time::Duration::ZERO;
+            }
+            while lag >= FRAME && fault.is_none() {
+                // emulated frames run at 60 Hz whatever the display refresh rate
+                if let Err(f) = self.run_frame() {
+                    println!("Stopped at {:#06X}: {f}", self.program_counter);
+                    fault = Some(f);
+                }
+                if let Some(r) = recorder.as_mut() {
+                    if let Err(e) = r.capture(self) {
+                        println!("Recording stopped: {e}");
+                        recorder = None;
+                    }
+                }
+                lag -= FRAME;
+            }
+            self.update_sound();
+            self.draw_screen();
+            let fps = get_fps();
+            draw_text(&format!("FPS: {fps}"), 80.0, 20.0, 20.0, WHITE);
+            if let Some(f) = fault {
+                draw_text(&format!("Stopped: {f}"), 80.0, 40.0, 20.0, RED);
+            }
+            if let Some((message, _)) = notice.as_ref().filter(|(_, at)| at.elapsed() < NOTICE) {
+                draw_text(message, 80.0, 60.0, 20.0, YELLOW);
+            }
+            if let Some(menu) = &cheat_menu {
+                menu.draw(self);
             }
+            macroquad::prelude::next_frame().await;
         }
     }
 
     pub fn dump_memory(&self) {
-        for (i, b) in self.memory.iter().enumerate() {
+        for (i, b) in self.memory.as_slice().iter().enumerate() {
             if i % 32 == 0 {
                 println!();
                 print!("{i:#06X}: ");
             }
-			if i % 8 == 0 {
-				print!("  ");
-			}
+            if i % 8 == 0 {
+                print!("  ");
+            }
             print!("{b:02X} ");
         }
     }
@@ -115,53 +259,59 @@ impl VM {
     }
 
     pub fn set_byte(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+        self.write_memory(address, &[value]);
     }
 }
 
 impl Default for VM {
     fn default() -> Self {
         let mut vm = Self {
-            memory: [0; 4096],
+            memory: bus::Bus::new(MemoryMode::Wrap4K),
             program_counter: 0x200,
             i: 0,
             reg: [0; 16],
             stack: [0; 16],
             stack_pointer: -1,
             key: [false; 16],
-            screen: [false; 64 * 32],
+            screen: Framebuffer::default(),
             delay_timer: 0,
             sound_timer: 0,
             sound_playing: false,
             key_pressed: None,
+            scaling: Scaling::Integer,
+            fullscreen: false,
+            palette: Palette::default(),
+            filter: Filter::None,
+            intensity: [0.0; 64 * 32],
+            previous_screen: [0; 32],
+            stale_rows: u32::MAX,
+            display: vec![0; RGBA_LEN],
+            texture: None,
+            timing: Timing::Fixed(crate::INSTRUCTIONS_PER_FRAME),
+            cycle_budget: 0,
+            quirks: Quirks::default(),
+            keymap: KEYMAP,
+            last_op: None,
+            profiler: None,
+            rng: random::DEFAULT_SEED,
+            cheats: Cheats::default(),
+            program_size: 0,
         };
-        vm.load_bytes(&FONTSET, 0);
+        vm.memory.as_mut_slice()[..FONTSET.len()].copy_from_slice(&FONTSET);
         vm
     }
 }
 
-#[cfg(test)]
-mod test {
-    use crate::VM;
-
-    #[test]
-    fn test_load_bytes() {
-        let mut vm = VM::new();
-        let buf = vec![0x00, 0xEE, 0x00, 0xE0, 0x10, 0x20];
-        vm.load_bytes(&buf, 0x200);
-        assert_eq!(vm.memory[0x200], 0x00);
-        assert_eq!(vm.memory[0x201], 0xEE);
+// an error when `len` bytes at `offset` would run past the end of memory
+fn fits(len: usize, offset: u16, memory: usize) -> io::Result<()> {
+    let space = memory.saturating_sub(usize::from(offset));
+    if len > space {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("ROM larger than memory: {len} bytes, {space} available"),
+        ));
     }
-
-	#[test]
-    fn test_load_font() {
-		let mut vm = VM::new();
-       
-        vm.load_bytes(&super::FONTSET, 0x0);
-        assert_eq!(vm.memory[0x00], 0xF0);
-        assert_eq!(vm.memory[0x01], 0x90);		
-        assert_eq!(vm.memory[5 * 4], 0x90);
-	}
+    Ok(())
 }
 
 static FONTSET: [u8; 80] = [
@@ -182,3 +332,75 @@ static FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+
+    #[test]
+    fn test_load_bytes() {
+        let mut vm = VM::new();
+        let buf = vec![0x00, 0xEE, 0x00, 0xE0, 0x10, 0x20];
+        vm.load_bytes(&buf, 0x200).unwrap();
+        assert_eq!(vm.memory()[0x200], 0x00);
+        assert_eq!(vm.memory()[0x201], 0xEE);
+
+        // a ROM that doesn't fit is refused and memory left alone
+        let err = vm.load_bytes(&[0xAA; 0xE01], 0x200).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ROM larger than memory: 3585 bytes, 3584 available"
+        );
+        assert_eq!(vm.memory()[0x200], 0x00);
+    }
+
+    #[test]
+    fn test_load_font() {
+        let mut vm = VM::new();
+
+        vm.load_bytes(&super::FONTSET, 0x0).unwrap();
+        assert_eq!(vm.memory()[0x00], 0xF0);
+        assert_eq!(vm.memory()[0x01], 0x90);
+        assert_eq!(vm.memory()[5 * 4], 0x90);
+    }
+
+    #[test]
+    fn test_reload() {
+        use crate::config::Config;
+        use crate::database::Rom;
+
+        // V0 = 5; I = 0x300; LD [I], V0; spin
+        let old = Rom::new(vec![0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06]);
+        let new = Rom::new(vec![0x61, 0x07, 0x12, 0x02]);
+        let mut vm = VM::new();
+        vm.load_rom(&old).unwrap();
+        vm.run_frame().unwrap();
+
+        vm.reload(&Config::default(), &new, true).unwrap();
+        assert_eq!(
+            (vm.register(0), vm.index(), vm.program_counter()),
+            (5, 0x300, 0x206)
+        );
+        assert_eq!(
+            vm.read_memory(0x200, 8),
+            &[0x61, 0x07, 0x12, 0x02, 0, 0, 0, 0]
+        );
+        assert_eq!(vm.memory()[0x300], 5);
+
+        vm.reload(&Config::default(), &new, false).unwrap();
+        assert_eq!(
+            (vm.register(0), vm.index(), vm.program_counter()),
+            (0, 0, 0x200)
+        );
+        assert_eq!(vm.memory()[0x300], 0);
+        vm.run_frame().unwrap();
+        assert_eq!(vm.register(1), 7);
+
+        // a rebuild that outgrows memory is refused either way
+        let huge = Rom::new(vec![0; 0x1000]);
+        assert!(vm.reload(&Config::default(), &huge, true).is_err());
+        assert!(vm.reload(&Config::default(), &huge, false).is_err());
+        assert_eq!(vm.read_memory(0x200, 2), &[0x61, 0x07]);
+        assert_eq!(vm.register(1), 7);
+    }
+}
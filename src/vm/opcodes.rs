@@ -1,6 +1,7 @@
-#[derive(Debug)]
-#[allow(clippy::upper_case_acronyms)]
+use super::Fault;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum OpCode {
     CLS,                            // 00E0
     RET,                            // 00EE
@@ -17,9 +18,9 @@ pub enum OpCode {
     RXOR { reg_x: u8, reg_y: u8 },  // 8XY3
     RADD { reg_x: u8, reg_y: u8 },  // 8XY4
     RSUB { reg_x: u8, reg_y: u8 },  // 8XY5
-    RSHR(u8),                       // 8XY6
+    RSHR { reg_x: u8, reg_y: u8 },  // 8XY6
     RSUBN { reg_x: u8, reg_y: u8 }, // 8XY7
-    RSHL(u8),                       // 8XYE
+    RSHL { reg_x: u8, reg_y: u8 },  // 8XYE
     RSNE { reg_x: u8, reg_y: u8 },  // 9XY0
     LD(u16),                        // ANNN
     JP(u16),                        // BNNN
@@ -40,209 +41,256 @@ pub enum OpCode {
 }
 
 impl OpCode {
+    // The decoder the VM runs. It never fails: anything that isn't a valid
+    // instruction decodes to `Unknown`. Unlike `try_from` it ignores the low nibble
+    // of 5XYN and 9XYN, as the VIP interpreter did, so those run as 5XY0 and 9XY0.
+    // For every other word `from_bytes(b).encode()` gives back the original.
     #[must_use]
     pub fn from_bytes(bytes: (u8, u8)) -> Self {
+        let word = u16::from_be_bytes([bytes.0, bytes.1]);
+        let canonical = match word >> 12 {
+            0x5 | 0x9 => word & 0xFFF0,
+            _ => word,
+        };
+        Self::try_from(canonical).unwrap_or(OpCode::Unknown(word))
+    }
+
+    // the 16-bit instruction word, as stored big-endian in memory
+    #[must_use]
+    pub fn encode(&self) -> u16 {
         use OpCode::{
             Unknown, ADD, ADDI, CALL, CLS, DRW, JMP, JP, KPR, LD, LDSPR, LDT, RADD, RAND, READ,
             RET, RLD, RND, ROR, RSE, RSHL, RSHR, RSNE, RSUB, RSUBN, RXOR, SE, SET, SETDT, SETST,
             SKNP, SKP, SNE, STBCD, STORE,
         };
-        match (bytes.0, bytes.1) {
-            (0x00, 0xE0) => CLS,
-            (0x00, 0xEE) => RET,
-            (0x10..=0x1F, _) => JMP(0x1000 ^ (((bytes.0 as u16) << 8) + bytes.1 as u16)),
-            (0x20..=0x2F, _) => CALL(0x2000 ^ (((bytes.0 as u16) << 8) + bytes.1 as u16)),
-            (0x30..=0x3F, _) => {
-                let reg = bytes.0 ^ 0x30;
-                let value = bytes.1;
-                SE { reg, value }
-            }
-            (0x40..=0x4F, _) => {
-                let reg = bytes.0 ^ 0x40;
-                let value = bytes.1;
-                SNE { reg, value }
-            }
-            (0x50..=0x5F, _) => {
-                let reg_x = bytes.0 ^ 0x50;
-                let reg_y = bytes.1 >> 4;
-                RSE { reg_x, reg_y }
-            }
-            (0x60..=0x6F, _) => {
-                let reg = bytes.0 ^ 0x60;
-                let value = bytes.1;
-                SET { reg, value }
-            }
-            (0x70..=0x7F, _) => {
-                let reg = bytes.0 ^ 0x70;
-                let value = bytes.1;
-                ADD { reg, value }
-            }
-            (0x80..=0x8F, _) => {
-                let reg_x = bytes.0 ^ 0x80;
-                let reg_y = bytes.1 >> 4;
-                let suffix = bytes.1 & 0x0F;
-                match suffix {
-                    0x00 => RLD { reg_x, reg_y },
-                    0x01 => ROR { reg_x, reg_y },
-                    0x02 => RAND { reg_x, reg_y },
-                    0x03 => RXOR { reg_x, reg_y },
-                    0x04 => RADD { reg_x, reg_y },
-                    0x05 => RSUB { reg_x, reg_y },
-                    0x06 => RSHR(reg_x),
-                    0x07 => RSUBN { reg_x, reg_y },
-                    0x0E => RSHL(reg_x),
-                    _ => unreachable!("OpCode 0x8XY{:1X}", suffix),
-                }
-            }
-            (0x90..=0x9F, _) => {
-                let reg_x = bytes.0 ^ 0x90;
-                let reg_y = bytes.1 >> 4;
-                RSNE { reg_x, reg_y }
-            }
-            (0xA0..=0xAF, _) => LD(0xA000 ^ (((bytes.0 as u16) << 8) + bytes.1 as u16)),
-            (0xB0..=0xBF, _) => JP(0xB000 ^ (((bytes.0 as u16) << 8) + bytes.1 as u16)),
-            (0xC0..=0xCF, _) => {
-                let reg = bytes.0 ^ 0xC0;
-                let value = bytes.1;
-                RND { reg, value }
-            }
-            (0xD0..=0xDF, _) => {
-                let x = bytes.0 ^ 0xD0;
-                let y = bytes.1 >> 4;
-                let n = bytes.1 & 0x0F;
-                DRW { x, y, n }
-            }
-            (0xE0..=0xEF, 0x9E) => SKP(bytes.0 ^ 0xE0),
-            (0xE0..=0xEF, 0xA1) => SKNP(bytes.0 ^ 0xE0),
-            (0xF0..=0xFF, 0x07) => LDT(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x0A) => KPR(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x15) => SETDT(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x18) => SETST(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x1E) => ADDI(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x29) => LDSPR(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x33) => STBCD(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x55) => STORE(bytes.0 ^ 0xF0),
-            (0xF0..=0xFF, 0x65) => READ(bytes.0 ^ 0xF0),
+        // high nibble, X, Y and low nibble; NNN fields are masked like the decoder reads them
+        let xy = |op: u16, x: u8, y: u8, n: u16| {
+            op << 12 | u16::from(x & 0xF) << 8 | u16::from(y & 0xF) << 4 | n
+        };
+        let xnn = |op: u16, x: u8, nn: u8| op << 12 | u16::from(x & 0xF) << 8 | u16::from(nn);
+        let fx = |x: u8, nn: u8| xnn(0xF, x, nn);
 
-            (_, _) => Unknown(((bytes.0 as u16) << 8) + bytes.1 as u16),
+        match *self {
+            CLS => 0x00E0,
+            RET => 0x00EE,
+            JMP(nnn) => 0x1000 | nnn & 0xFFF,
+            CALL(nnn) => 0x2000 | nnn & 0xFFF,
+            SE { reg, value } => xnn(0x3, reg, value),
+            SNE { reg, value } => xnn(0x4, reg, value),
+            RSE { reg_x, reg_y } => xy(0x5, reg_x, reg_y, 0x0),
+            SET { reg, value } => xnn(0x6, reg, value),
+            ADD { reg, value } => xnn(0x7, reg, value),
+            RLD { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x0),
+            ROR { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x1),
+            RAND { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x2),
+            RXOR { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x3),
+            RADD { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x4),
+            RSUB { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x5),
+            RSHR { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x6),
+            RSUBN { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0x7),
+            RSHL { reg_x, reg_y } => xy(0x8, reg_x, reg_y, 0xE),
+            RSNE { reg_x, reg_y } => xy(0x9, reg_x, reg_y, 0x0),
+            LD(nnn) => 0xA000 | nnn & 0xFFF,
+            JP(nnn) => 0xB000 | nnn & 0xFFF,
+            RND { reg, value } => xnn(0xC, reg, value),
+            DRW { x, y, n } => xy(0xD, x, y, u16::from(n & 0xF)),
+            SKP(x) => xnn(0xE, x, 0x9E),
+            SKNP(x) => xnn(0xE, x, 0xA1),
+            LDT(x) => fx(x, 0x07),
+            KPR(x) => fx(x, 0x0A),
+            SETDT(x) => fx(x, 0x15),
+            SETST(x) => fx(x, 0x18),
+            ADDI(x) => fx(x, 0x1E),
+            LDSPR(x) => fx(x, 0x29),
+            STBCD(x) => fx(x, 0x33),
+            STORE(x) => fx(x, 0x55),
+            READ(x) => fx(x, 0x65),
+            Unknown(word) => word,
         }
     }
-}
 
-impl std::fmt::Display for OpCode {
-    #[allow(clippy::too_many_lines)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    #[must_use]
+    pub fn mnemonic(&self) -> &'static str {
         use OpCode::{
             Unknown, ADD, ADDI, CALL, CLS, DRW, JMP, JP, KPR, LD, LDSPR, LDT, RADD, RAND, READ,
             RET, RLD, RND, ROR, RSE, RSHL, RSHR, RSNE, RSUB, RSUBN, RXOR, SE, SET, SETDT, SETST,
             SKNP, SKP, SNE, STBCD, STORE,
         };
         match self {
-            CLS => Ok(write!(f, "CLS - {:#06X}", 0x00E0)?),
-            RET => Ok(write!(f, "RET - {:#06X}", 0x00EE)?),
-            JMP(x) => Ok(write!(f, "JMP - {:#06X}", x ^ 0x1000)?),
-            CALL(x) => Ok(write!(f, "CALL - {:#06X}", x ^ 0x2000)?),
-            SE { reg, value } => Ok(write!(
-                f,
-                "SE - {:#06X}",
-                (((*reg as u16) << 8) + *value as u16) ^ 0x3000
-            )?),
-            SNE { reg, value } => Ok(write!(
-                f,
-                "SNE - {:#06X}",
-                (((*reg as u16) << 8) + *value as u16) ^ 0x4000
-            )?),
-            RSE { reg_x, reg_y } => Ok(write!(
-                f,
-                "RSE - {:#06X}",
-                (((*reg_x as u16) << 8) + (reg_y << 4) as u16) ^ 0x4000
-            )?),
-            RSNE { reg_x, reg_y } => Ok(write!(
-                f,
-                "RSNE - {:#06X}",
-                (((*reg_x as u16) << 8) + (reg_y << 4) as u16) ^ 0x9000
-            )?),
-            SET { reg, value } => Ok(write!(
-                f,
-                "SET - {:#06X}",
-                (((*reg as u16) << 8) + *value as u16) ^ 0x6000
-            )?),
-            ADD { reg, value } => Ok(write!(
-                f,
-                "ADD - {:#06X}",
-                (((*reg as u16) << 8) + *value as u16) ^ 0x7000
-            )?),
-            RLD { reg_x, reg_y } => Ok(write!(
-                f,
-                "RLD - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4))
-            )?),
-            ROR { reg_x, reg_y } => Ok(write!(
-                f,
-                "ROR - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x01)
-            )?),
-            RAND { reg_x, reg_y } => Ok(write!(
-                f,
-                "RAND - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x02)
-            )?),
-            RXOR { reg_x, reg_y } => Ok(write!(
-                f,
-                "RXOR - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x03)
-            )?),
-            RADD { reg_x, reg_y } => Ok(write!(
-                f,
-                "RADD - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x04)
-            )?),
-            RSUB { reg_x, reg_y } => Ok(write!(
-                f,
-                "RSUB - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x05)
-            )?),
-            RSHR(reg) => Ok(write!(
-                f,
-                "RSHR - {:#06X}",
-                0x8000 ^ (((*reg as u16) << 8) + 0x06)
-            )?),
-            RSUBN { reg_x, reg_y } => Ok(write!(
-                f,
-                "RSUBN - {:#06X}",
-                0x8000 ^ (((*reg_x as u16) << 8) + ((*reg_y as u16) << 4) + 0x07)
-            )?),
-            RSHL(reg) => Ok(write!(
-                f,
-                "RSHL - {:#06X}",
-                0x8000 ^ (((*reg as u16) << 8) + 0x0E)
-            )?),
-            LD(x) => Ok(write!(f, "LD - {:#06X}", x ^ 0xA000)?),
-            JP(x) => Ok(write!(f, "JP - {:#06X}", x ^ 0xB000)?),
-            RND { reg, value } => Ok(write!(
-                f,
-                "RND - {:#06X}",
-                (((*reg as u16) << 8) + *value as u16) ^ 0xC000
-            )?),
-            DRW { x, y, n } => Ok(write!(
-                f,
-                "DRW - {:#06X}",
-                (((*x as u16) << 8) + ((y << 4) + n) as u16) ^ 0xD000
-            )?),
-            SKP(x) => Ok(write!(f, "SKP - {:#06X}", ((*x as u16) << 8) ^ 0xE09E)?),
-            SKNP(x) => Ok(write!(f, "SKNP - {:#06X}", ((*x as u16) << 8) ^ 0xE0A1)?),
-            LDT(x) => Ok(write!(f, "LDT - {:#06X}", ((*x as u16) << 8) ^ 0xF007)?),
-            KPR(x) => Ok(write!(f, "KPR - {:#06X}", ((*x as u16) << 8) ^ 0xF00A)?),
+            CLS => "CLS",
+            RET => "RET",
+            JMP(_) => "JMP",
+            CALL(_) => "CALL",
+            SE { .. } => "SE",
+            SNE { .. } => "SNE",
+            RSE { .. } => "RSE",
+            SET { .. } => "SET",
+            ADD { .. } => "ADD",
+            RLD { .. } => "RLD",
+            ROR { .. } => "ROR",
+            RAND { .. } => "RAND",
+            RXOR { .. } => "RXOR",
+            RADD { .. } => "RADD",
+            RSUB { .. } => "RSUB",
+            RSHR { .. } => "RSHR",
+            RSUBN { .. } => "RSUBN",
+            RSHL { .. } => "RSHL",
+            RSNE { .. } => "RSNE",
+            LD(_) => "LD",
+            JP(_) => "JP",
+            RND { .. } => "RND",
+            DRW { .. } => "DRW",
+            SKP(_) => "SKP",
+            SKNP(_) => "SKNP",
+            LDT(_) => "LDT",
+            KPR(_) => "KPR",
+            SETDT(_) => "SETDT",
+            SETST(_) => "SETST",
+            ADDI(_) => "ADDI",
+            LDSPR(_) => "LDSPR",
+            STBCD(_) => "STBCD",
+            STORE(_) => "STORE",
+            READ(_) => "READ",
+            Unknown(_) => "UNK",
+        }
+    }
+}
 
-            SETDT(x) => Ok(write!(f, "SETDT - {:#06X}", ((*x as u16) << 8) ^ 0xF015)?),
-            SETST(x) => Ok(write!(f, "SETST - {:#06X}", ((*x as u16) << 8) ^ 0xF018)?),
-            ADDI(x) => Ok(write!(f, "ADDI - {:#06X}", ((*x as u16) << 8) ^ 0xF01E)?),
-            LDSPR(x) => Ok(write!(f, "LDSPR - {:#06X}", ((*x as u16) << 8) ^ 0xF029)?),
-            STBCD(x) => Ok(write!(f, "STBCD - {:#06X}", ((*x as u16) << 8) ^ 0xF033)?),
-            STORE(x) => Ok(write!(f, "STORE - {:#06X}", ((*x as u16) << 8) ^ 0xF055)?),
-            READ(x) => Ok(write!(f, "READ - {:#06X}", ((*x as u16) << 8) ^ 0xF065)?),
+// Strict decoding: only the canonical encoding of an instruction is accepted, so
+// 5XY1 or 8XYF are errors rather than aliases of 5XY0 or a panic.
+impl TryFrom<u16> for OpCode {
+    type Error = Fault;
 
-            Unknown(x) => Ok(write!(f, "UNK - {x:#06X}")?),
+    fn try_from(word: u16) -> Result<Self, Self::Error> {
+        use OpCode::{
+            ADD, ADDI, CALL, CLS, DRW, JMP, JP, KPR, LD, LDSPR, LDT, RADD, RAND, READ, RET, RLD,
+            RND, ROR, RSE, RSHL, RSHR, RSNE, RSUB, RSUBN, RXOR, SE, SET, SETDT, SETST, SKNP, SKP,
+            SNE, STBCD, STORE,
+        };
+        let x = ((word >> 8) & 0xF) as u8;
+        let y = ((word >> 4) & 0xF) as u8;
+        let n = (word & 0xF) as u8;
+        let nn = (word & 0xFF) as u8;
+        let nnn = word & 0xFFF;
+        let (reg_x, reg_y) = (x, y);
+
+        let op = match (word >> 12, n) {
+            (0x0, _) if word == 0x00E0 => CLS,
+            (0x0, _) if word == 0x00EE => RET,
+            (0x1, _) => JMP(nnn),
+            (0x2, _) => CALL(nnn),
+            (0x3, _) => SE { reg: x, value: nn },
+            (0x4, _) => SNE { reg: x, value: nn },
+            (0x5, 0x0) => RSE { reg_x, reg_y },
+            (0x6, _) => SET { reg: x, value: nn },
+            (0x7, _) => ADD { reg: x, value: nn },
+            (0x8, 0x0) => RLD { reg_x, reg_y },
+            (0x8, 0x1) => ROR { reg_x, reg_y },
+            (0x8, 0x2) => RAND { reg_x, reg_y },
+            (0x8, 0x3) => RXOR { reg_x, reg_y },
+            (0x8, 0x4) => RADD { reg_x, reg_y },
+            (0x8, 0x5) => RSUB { reg_x, reg_y },
+            (0x8, 0x6) => RSHR { reg_x, reg_y },
+            (0x8, 0x7) => RSUBN { reg_x, reg_y },
+            (0x8, 0xE) => RSHL { reg_x, reg_y },
+            (0x9, 0x0) => RSNE { reg_x, reg_y },
+            (0xA, _) => LD(nnn),
+            (0xB, _) => JP(nnn),
+            (0xC, _) => RND { reg: x, value: nn },
+            (0xD, _) => DRW { x, y, n },
+            (0xE, _) if nn == 0x9E => SKP(x),
+            (0xE, _) if nn == 0xA1 => SKNP(x),
+            (0xF, _) => match nn {
+                0x07 => LDT(x),
+                0x0A => KPR(x),
+                0x15 => SETDT(x),
+                0x18 => SETST(x),
+                0x1E => ADDI(x),
+                0x29 => LDSPR(x),
+                0x33 => STBCD(x),
+                0x55 => STORE(x),
+                0x65 => READ(x),
+                _ => return Err(Fault::UnknownOpcode(word)),
+            },
+            _ => return Err(Fault::UnknownOpcode(word)),
+        };
+        Ok(op)
+    }
+}
+
+impl From<OpCode> for u16 {
+    fn from(op: OpCode) -> Self {
+        op.encode()
+    }
+}
+
+impl std::fmt::Display for OpCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} - {:#06X}", self.mnemonic(), self.encode())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OpCode;
+    use crate::vm::Fault;
+
+    #[test]
+    fn test_every_word_round_trips() {
+        let mut valid = 0;
+        for word in 0..=u16::MAX {
+            let [hi, lo] = word.to_be_bytes();
+            let canonical = match word >> 12 {
+                0x5 | 0x9 => word & 0xFFF0,
+                _ => word,
+            };
+            assert_eq!(OpCode::from_bytes((hi, lo)).encode(), canonical);
+            if let Ok(op) = OpCode::try_from(word) {
+                assert_eq!(op.encode(), word, "{op:?}");
+                assert!(!matches!(op, OpCode::Unknown(_)));
+                valid += 1;
+            }
         }
+        // 00E0/00EE, 10 NNN/XNN/XYN families of 4096, 5XY0/9XY0 and nine 8XY? of 256,
+        // and two EX?? and nine FX?? of 16
+        assert_eq!(valid, 2 + 10 * 4096 + 11 * 256 + 11 * 16);
+    }
+
+    #[test]
+    fn test_strict_decoding() {
+        assert_eq!(OpCode::try_from(0x5121), Err(Fault::UnknownOpcode(0x5121)));
+        assert_eq!(OpCode::try_from(0x912F), Err(Fault::UnknownOpcode(0x912F)));
+        assert_eq!(OpCode::from_bytes((0x81, 0x2F)), OpCode::Unknown(0x812F));
+        // the VM still runs the 5XYN and 9XYN aliases strict decoding rejects
+        assert_eq!(
+            OpCode::from_bytes((0x51, 0x21)),
+            OpCode::RSE { reg_x: 1, reg_y: 2 }
+        );
+        assert_eq!(
+            OpCode::from_bytes((0x91, 0x2F)),
+            OpCode::RSNE { reg_x: 1, reg_y: 2 }
+        );
+        assert_eq!(
+            OpCode::try_from(0x5120),
+            Ok(OpCode::RSE { reg_x: 1, reg_y: 2 })
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            OpCode::RSE { reg_x: 1, reg_y: 2 }.to_string(),
+            "RSE - 0x5120"
+        );
+        assert_eq!(
+            OpCode::RSNE {
+                reg_x: 0xA,
+                reg_y: 0xB
+            }
+            .to_string(),
+            "RSNE - 0x9AB0"
+        );
+        assert_eq!(OpCode::DRW { x: 1, y: 2, n: 3 }.to_string(), "DRW - 0xD123");
+        assert_eq!(OpCode::Unknown(0xFFFF).to_string(), "UNK - 0xFFFF");
     }
 }
@@ -1,14 +1,17 @@
+use super::framebuffer::Row;
+use super::Fault;
 use crate::{SCREEN_HEIGHT, SCREEN_WIDTH, VM};
 
 impl VM {
     // CLS - 00E0
     pub fn clear_display(&mut self) {
-        self.screen.iter_mut().for_each(|x| *x = false);
+        self.screen.clear();
     }
 
     // RET - 00EE
-    pub fn return_subroutine(&mut self) {
-        self.program_counter = self.pop();
+    pub fn return_subroutine(&mut self) -> Result<(), Fault> {
+        self.program_counter = self.pop()?;
+        Ok(())
     }
 
     // JMP - 1NNN
@@ -17,29 +20,30 @@ impl VM {
     }
 
     // CALL - 2NNN
-    pub fn call(&mut self, addr: u16) {
-        self.push(self.program_counter);
+    pub fn call(&mut self, addr: u16) -> Result<(), Fault> {
+        self.push(self.program_counter)?;
         self.program_counter = addr;
+        Ok(())
     }
 
     // SE - 3XNN
     pub fn skip_equal(&mut self, register: u8, value: u8) {
         if self.reg[register as usize] == value {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
     // SNE - 4XNN
     pub fn skip_not_equal(&mut self, register: u8, value: u8) {
         if self.reg[register as usize] != value {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
     // RSE - 5XY0
     pub fn reg_skip_equal(&mut self, reg1: u8, reg2: u8) {
         if self.reg[reg1 as usize] == self.reg[reg2 as usize] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
@@ -61,16 +65,25 @@ impl VM {
     // ROR - 8XY1
     pub fn reg_or(&mut self, reg_x: u8, reg_y: u8) {
         self.reg[reg_x as usize] |= self.reg[reg_y as usize];
+        if self.quirks.logic {
+            self.set_carry_flag(0);
+        }
     }
 
     // RAND - 8XY2
     pub fn reg_and(&mut self, reg_x: u8, reg_y: u8) {
         self.reg[reg_x as usize] &= self.reg[reg_y as usize];
+        if self.quirks.logic {
+            self.set_carry_flag(0);
+        }
     }
 
     // RXOR - 8XY3
     pub fn reg_xor(&mut self, reg_x: u8, reg_y: u8) {
         self.reg[reg_x as usize] ^= self.reg[reg_y as usize];
+        if self.quirks.logic {
+            self.set_carry_flag(0);
+        }
     }
 
     // RADD - 8XY4
@@ -104,9 +117,9 @@ impl VM {
     }
 
     // RSHR - 8XY6
-    pub fn reg_shift_right(&mut self, reg: u8) {
-        let v = self.reg[reg as usize];
-        self.reg[reg as usize] = v >> 1;
+    pub fn reg_shift_right(&mut self, reg_x: u8, reg_y: u8) {
+        let v = self.reg[if self.quirks.shift { reg_x } else { reg_y } as usize];
+        self.reg[reg_x as usize] = v >> 1;
 
         if 0b0000_0001 & v == 1 {
             self.set_carry_flag(1);
@@ -130,10 +143,10 @@ impl VM {
     }
 
     // RSHL - 8XYE
-    pub fn reg_shift_left(&mut self, reg: u8) {
-        let v = self.reg[reg as usize];
+    pub fn reg_shift_left(&mut self, reg_x: u8, reg_y: u8) {
+        let v = self.reg[if self.quirks.shift { reg_x } else { reg_y } as usize];
 
-        self.reg[reg as usize] = v << 1;
+        self.reg[reg_x as usize] = v << 1;
 
         if 0b1000_0000 & v == 0b1000_0000 {
             self.set_carry_flag(1);
@@ -145,7 +158,7 @@ impl VM {
     // RSNE - 9XY0
     pub fn reg_skip_not_equal(&mut self, reg1: u8, reg2: u8) {
         if self.reg[reg1 as usize] != self.reg[reg2 as usize] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
@@ -156,50 +169,57 @@ impl VM {
 
     // JP - BNNN
     pub fn jump_location(&mut self, value: u16) {
-        self.program_counter = self.reg[0x0] as u16 + value;
+        let reg = if self.quirks.jump { value >> 8 } else { 0x0 };
+        self.program_counter = self.reg[reg as usize] as u16 + value;
     }
 
     // RND - CXNN
     pub fn random(&mut self, reg: u8, value: u8) {
-        let r = macroquad::rand::gen_range(0, 255);
+        let r = self.random_byte();
         self.reg[reg as usize] = r & value;
     }
 
     // DRW - DXYN
-    pub fn draw(&mut self, x: u8, y: u8, n: u8) {
-        let addr = self.i;
-        let x = self.reg[x as usize] % SCREEN_WIDTH as u8;
+    pub fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), Fault> {
+        let x = u32::from(self.reg[x as usize]) % SCREEN_WIDTH;
         let y = self.reg[y as usize] % SCREEN_HEIGHT as u8;
-        for i in 0..n {
-            let data = self.memory[addr as usize + i as usize];
-            for j in 0..8 {
-                let x = (x + j) % SCREEN_WIDTH as u8;
-                let y = (y + i) % SCREEN_HEIGHT as u8;
-                if 0b1000_0000 >> j & data != 0 {
-                    let r = self.set_pixel(x, y);
-                    if r {
-                        self.set_carry_flag(1);
-                    } else {
-                        self.set_carry_flag(0);
-                    }
-                }
-            }
+        let rows = if self.quirks.wrap {
+            n
+        } else {
+            n.min(SCREEN_HEIGHT as u8 - y)
+        };
+        self.memory.check(u32::from(self.i), u32::from(rows))?;
+        let mut collision = false;
+        for i in 0..rows {
+            // sprite byte moved to the left edge, then shifted right into place;
+            // pixels past the right edge are clipped, or wrap to the left one
+            let data = self.memory.read(u32::from(self.i) + u32::from(i))?;
+            let sprite = (Row::from(data) << (Row::BITS - 8)) >> x;
+            let bits = if self.quirks.wrap {
+                sprite | sprite << SCREEN_WIDTH
+            } else {
+                sprite
+            };
+            collision |= self.screen.xor_row(y + i, bits);
         }
+        self.set_carry_flag(u8::from(collision));
+        Ok(())
     }
 
     // SKP - EX9E
+    // only the low nibble of VX selects a key, as on the VIP keypad latch
     pub fn skip_if_key(&mut self, reg: u8) {
-        let key = self.reg[reg as usize];
+        let key = self.reg[reg as usize] & 0xF;
         if self.key[key as usize] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
     // SKNP - EXA1
     pub fn skip_if_no_key(&mut self, reg: u8) {
-        let key = self.reg[reg as usize];
+        let key = self.reg[reg as usize] & 0xF;
         if !self.key[key as usize] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
@@ -221,12 +241,12 @@ impl VM {
                         self.key_pressed = Some(i as u8);
                     }
                 }
-                self.program_counter -= 2;
+                self.program_counter = self.program_counter.wrapping_sub(2);
             }
             Some(k) => {
                 // KEY DOWN, WAITING KEY UP
                 if self.key[k as usize] {
-                    self.program_counter -= 2;
+                    self.program_counter = self.program_counter.wrapping_sub(2);
                 } else {
                     self.key_pressed = None;
                 }
@@ -246,7 +266,7 @@ impl VM {
 
     // ADDI - FX1E
     pub fn add_i(&mut self, reg: u8) {
-        self.i += self.reg[reg as usize] as u16;
+        self.i = self.i.wrapping_add(u16::from(self.reg[reg as usize]));
     }
 
     // LDSPR - FX29
@@ -256,30 +276,43 @@ impl VM {
     }
 
     // STBCD - FX33
-    pub fn store_bcd(&mut self, reg: u8) {
+    pub fn store_bcd(&mut self, reg: u8) -> Result<(), Fault> {
         let val = self.reg[reg as usize];
-        let addr = self.i as usize;
-        let d1 = val / 100;
-        let d2 = (val % 100) / 10;
-        let d3 = val % 10;
-        self.memory[addr] = d1;
-        self.memory[addr + 1] = d2;
-        self.memory[addr + 2] = d3;
+        let addr = u32::from(self.i);
+        self.memory.check(addr, 3)?;
+        self.memory.write(addr, val / 100)?;
+        self.memory.write(addr + 1, (val % 100) / 10)?;
+        self.memory.write(addr + 2, val % 10)
     }
 
     // STORE - FX55
-    pub fn store_registers(&mut self, reg: u8) {
-        let addr = self.i;
-        for i in 0..=(reg as usize) {
-            self.memory[(addr as usize) + i] = self.reg[i];
+    pub fn store_registers(&mut self, reg: u8) -> Result<(), Fault> {
+        let addr = u32::from(self.i);
+        self.memory.check(addr, u32::from(reg) + 1)?;
+        for i in 0..=reg {
+            self.memory
+                .write(addr + u32::from(i), self.reg[i as usize])?;
         }
+        self.advance_i_after_load_store(reg);
+        Ok(())
     }
 
     // READ - FX65
-    pub fn read_registers(&mut self, reg: u8) {
-        let addr = self.i;
-        for i in 0..=(reg as usize) {
-            self.reg[i] = self.memory[(addr as usize) + i];
+    pub fn read_registers(&mut self, reg: u8) -> Result<(), Fault> {
+        let addr = u32::from(self.i);
+        self.memory.check(addr, u32::from(reg) + 1)?;
+        for i in 0..=reg {
+            self.reg[i as usize] = self.memory.read(addr + u32::from(i))?;
+        }
+        self.advance_i_after_load_store(reg);
+        Ok(())
+    }
+
+    fn advance_i_after_load_store(&mut self, reg: u8) {
+        if self.quirks.memory_increment_by_x {
+            self.i = self.i.wrapping_add(u16::from(reg));
+        } else if !self.quirks.memory_leave_i_unchanged {
+            self.i = self.i.wrapping_add(u16::from(reg) + 1);
         }
     }
 }
@@ -0,0 +1,286 @@
+// Opt-in instruction profiler. Counts are in executed instructions, which is what
+// a ROM can control; real time depends on the frontend's timing mode.
+use crate::symbols::{describe, Symbols};
+use crate::{OpCode, VM};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallStats {
+    pub calls: u64,
+    pub inclusive: u64, // instructions executed while the subroutine was anywhere on the stack
+    pub exclusive: u64, // instructions executed in the subroutine itself
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    instructions: u64,
+    addresses: HashMap<u16, (u64, OpCode)>,
+    opcodes: BTreeMap<&'static str, u64>,
+    subroutines: HashMap<u16, CallStats>,
+    stacks: HashMap<Vec<u16>, u64>, // call targets from the outermost in, for folded output
+    draws: Vec<u32>,                // DRW count of every finished frame
+    frame_draws: u32,
+    frames: Vec<u16>, // scratch buffer for the current call chain
+}
+
+// Subroutine each stack entry belongs to: the target of the CALL just before the
+// return address, or the call site itself if that isn't a CALL any more.
+fn call_chain(vm: &VM, out: &mut Vec<u16>) {
+    out.clear();
+    for &ret in vm.stack() {
+        let site = ret.wrapping_sub(2);
+        let bytes = vm.read_memory(site, 2);
+        let target = match bytes {
+            &[hi, lo] => match OpCode::from_bytes((hi, lo)) {
+                OpCode::CALL(target) => target,
+                _ => site,
+            },
+            _ => site,
+        };
+        out.push(target);
+    }
+}
+
+impl Profiler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // called before `op`, fetched from `pc`, is executed
+    fn record(&mut self, vm: &VM, pc: u16, op: OpCode) {
+        self.instructions += 1;
+        self.addresses.entry(pc).or_insert((0, op)).0 += 1;
+        *self.opcodes.entry(op.mnemonic()).or_default() += 1;
+
+        let mut frames = std::mem::take(&mut self.frames);
+        call_chain(vm, &mut frames);
+        if let OpCode::CALL(target) = op {
+            self.subroutines.entry(target).or_default().calls += 1;
+        }
+        if let Some(&top) = frames.last() {
+            self.subroutines.entry(top).or_default().exclusive += 1;
+        }
+        for (i, target) in frames.iter().enumerate() {
+            // recursion counts once per instruction
+            if !frames[..i].contains(target) {
+                self.subroutines.entry(*target).or_default().inclusive += 1;
+            }
+        }
+        match self.stacks.get_mut(frames.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(frames.clone(), 1);
+            }
+        }
+        self.frames = frames;
+
+        if matches!(op, OpCode::DRW { .. }) {
+            self.frame_draws += 1;
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.draws.push(std::mem::take(&mut self.frame_draws));
+    }
+
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    #[must_use]
+    pub fn address_count(&self, pc: u16) -> u64 {
+        self.addresses.get(&pc).map_or(0, |&(count, _)| count)
+    }
+
+    #[must_use]
+    pub fn opcode_count(&self, mnemonic: &str) -> u64 {
+        self.opcodes.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn subroutine(&self, target: u16) -> CallStats {
+        self.subroutines.get(&target).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn draws_per_frame(&self) -> &[u32] {
+        &self.draws
+    }
+
+    // Human readable report, each table sorted by count and cut to `top` rows.
+    // Addresses are shown as labels when there are symbols.
+    #[must_use]
+    pub fn report(&self, top: usize, symbols: Option<&Symbols>) -> String {
+        let total = self.instructions.max(1) as f64;
+        let percent = |n: u64| n as f64 * 100.0 / total;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} instructions over {} frames\n",
+            self.instructions,
+            self.draws.len()
+        );
+
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "ADDRESS     COUNT       %  INSTRUCTION");
+        for (pc, (count, op)) in addresses.into_iter().take(top) {
+            let _ = write!(out, "{pc:#06X} {count:>10} {:>6.2}  {op}", percent(*count));
+            if let Some(symbols) = symbols {
+                let _ = write!(out, "  ({})", symbols.describe(*pc));
+            }
+            out.push('\n');
+        }
+
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "\nOPCODE      COUNT       %");
+        for (name, count) in opcodes {
+            let _ = writeln!(out, "{name:<6} {count:>10} {:>6.2}", percent(*count));
+        }
+
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+        let _ = writeln!(
+            out,
+            "\nSUBROUTINE  CALLS   INCLUSIVE       %   EXCLUSIVE       %"
+        );
+        for (target, s) in subroutines.into_iter().take(top) {
+            let _ = write!(
+                out,
+                "{target:#06X} {:>10} {:>11} {:>7.2} {:>11} {:>7.2}",
+                s.calls,
+                s.inclusive,
+                percent(s.inclusive),
+                s.exclusive,
+                percent(s.exclusive)
+            );
+            if let Some(symbols) = symbols {
+                let _ = write!(out, "  {}", symbols.describe(*target));
+            }
+            out.push('\n');
+        }
+
+        let frames = self.draws.len().max(1) as f64;
+        let draws: u64 = self.draws.iter().map(|&d| u64::from(d)).sum();
+        let max = self.draws.iter().max().copied().unwrap_or(0);
+        let _ = writeln!(
+            out,
+            "\nDRW per frame: {:.2} average, {max} max",
+            draws as f64 / frames
+        );
+        out
+    }
+
+    // One line per distinct call chain, `main;0x0300;0x0342 count`, as read by
+    // flamegraph.pl and inferno. With symbols the frames are labels instead.
+    pub fn write_folded<W: Write>(
+        &self,
+        mut writer: W,
+        symbols: Option<&Symbols>,
+    ) -> io::Result<()> {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(frames, count)| {
+                let mut line = String::from("main");
+                for &target in frames {
+                    let _ = write!(line, ";{}", describe(symbols, target));
+                }
+                format!("{line} {count}")
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            writeln!(writer, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl VM {
+    // starts profiling from the next instruction, discarding any previous profile
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Box::default());
+    }
+
+    #[must_use]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    // stops profiling and hands back what was collected
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take().map(|p| *p)
+    }
+
+    pub(crate) fn profile_op(&mut self, op: OpCode) {
+        if let Some(mut profiler) = self.profiler.take() {
+            // execute_op runs after the fetch has moved PC on
+            profiler.record(self, self.program_counter.wrapping_sub(2), op);
+            self.profiler = Some(profiler);
+        }
+    }
+
+    pub(crate) fn profile_frame(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::symbols::Symbols;
+    use crate::{Timing, VM};
+
+    #[test]
+    fn test_profile_subroutines() {
+        let mut vm = VM::new();
+        // 0x200 CALL 0x206; JMP 0x200; (pad); 0x206 DRW V0, V0, 1; CALL 0x20C; RET;
+        // 0x20C ADD V1, 1; RET
+        vm.load_bytes(
+            &[
+                0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0xD0, 0x01, 0x22, 0x0C, 0x00, 0xEE, 0x71, 0x01,
+                0x00, 0xEE,
+            ],
+            0x200,
+        )
+        .unwrap();
+        vm.set_timing(Timing::Fixed(7));
+        vm.enable_profiler();
+        vm.run_frame().unwrap();
+        vm.run_frame().unwrap();
+
+        let p = vm.profiler().unwrap();
+        assert_eq!(p.instructions(), 14);
+        assert_eq!(p.address_count(0x200), 2);
+        assert_eq!(p.opcode_count("RET"), 4);
+        assert_eq!(p.draws_per_frame(), &[1, 1]);
+
+        let outer = p.subroutine(0x206);
+        assert_eq!((outer.calls, outer.inclusive, outer.exclusive), (2, 10, 6));
+        let inner = p.subroutine(0x20C);
+        assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (2, 4, 4));
+
+        let mut folded = vec![];
+        p.write_folded(&mut folded, None).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "main 4\nmain;0x0206 6\nmain;0x0206;0x020C 4\n"
+        );
+        assert!(p.report(10, None).contains("0x0206          2"));
+
+        let symbols = Symbols::parse("0x206 draw\n0x20C score").unwrap();
+        let mut folded = vec![];
+        p.write_folded(&mut folded, Some(&symbols)).unwrap();
+        assert!(String::from_utf8(folded)
+            .unwrap()
+            .contains("main;draw;score 4\n"));
+        assert!(p.report(10, Some(&symbols)).contains("  (draw+0x02)\n"));
+    }
+}
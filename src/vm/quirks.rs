@@ -0,0 +1,219 @@
+use serde::Deserialize;
+
+// Behaviours that differ between CHIP-8 interpreters, named after the
+// community chip-8-database so entries can be deserialised directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Quirks {
+    pub shift: bool,                    // 8XY6/8XYE shift VX in place instead of VY
+    pub memory_increment_by_x: bool,    // FX55/FX65 leave I at I + X
+    pub memory_leave_i_unchanged: bool, // FX55/FX65 don't touch I at all
+    pub wrap: bool,                     // DXYN wraps sprites around the edges instead of clipping
+    pub jump: bool,                     // BXNN jumps to XNN + VX instead of NNN + V0
+    pub vblank: bool,                   // DXYN waits for the next frame
+    pub logic: bool,                    // 8XY1/8XY2/8XY3 reset VF
+}
+
+impl Default for Quirks {
+    // what this interpreter has always done
+    fn default() -> Self {
+        Self {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: true,
+            jump: false,
+            vblank: false,
+            logic: false,
+        }
+    }
+}
+
+// quirks that a ROM database entry or config file changes, leaving the rest alone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct QuirkOverrides {
+    pub shift: Option<bool>,
+    pub memory_increment_by_x: Option<bool>,
+    pub memory_leave_i_unchanged: Option<bool>,
+    pub wrap: Option<bool>,
+    pub jump: Option<bool>,
+    pub vblank: Option<bool>,
+    pub logic: Option<bool>,
+}
+
+impl QuirkOverrides {
+    pub fn apply(&self, quirks: &mut Quirks) {
+        quirks.shift = self.shift.unwrap_or(quirks.shift);
+        quirks.memory_increment_by_x = self
+            .memory_increment_by_x
+            .unwrap_or(quirks.memory_increment_by_x);
+        quirks.memory_leave_i_unchanged = self
+            .memory_leave_i_unchanged
+            .unwrap_or(quirks.memory_leave_i_unchanged);
+        quirks.wrap = self.wrap.unwrap_or(quirks.wrap);
+        quirks.jump = self.jump.unwrap_or(quirks.jump);
+        quirks.vblank = self.vblank.unwrap_or(quirks.vblank);
+        quirks.logic = self.logic.unwrap_or(quirks.logic);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl Platform {
+    // quirks of the reference interpreter for each platform
+    #[must_use]
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: false,
+                vblank: true,
+                logic: true,
+            },
+            Platform::Schip => Quirks {
+                shift: true,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: true,
+                wrap: false,
+                jump: true,
+                vblank: false,
+                logic: false,
+            },
+            Platform::XoChip => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: true,
+                jump: false,
+                vblank: false,
+                logic: false,
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::Schip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform {s}, expected chip8, schip or xochip"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+impl super::VM {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    #[must_use]
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Platform;
+    use crate::VM;
+
+    #[test]
+    fn test_shift_quirk() {
+        let mut vm = VM::new();
+        vm.set_register(1, 0b0000_0011);
+        vm.set_register(2, 0b1000_0000);
+        vm.reg_shift_right(1, 2);
+        assert_eq!(vm.reg[1], 0b0000_0001);
+        assert_eq!(vm.reg[0xF], 1);
+
+        vm.set_quirks(Platform::Chip8.quirks());
+        vm.reg_shift_left(1, 2);
+        assert_eq!(vm.reg[1], 0);
+        assert_eq!(vm.reg[0xF], 1);
+    }
+
+    #[test]
+    fn test_memory_quirks() {
+        let mut vm = VM::new();
+        vm.set_index(0x300);
+        vm.store_registers(3).unwrap();
+        assert_eq!(vm.i, 0x300);
+
+        vm.set_quirks(Platform::Chip8.quirks());
+        vm.read_registers(3).unwrap();
+        assert_eq!(vm.i, 0x304);
+    }
+
+    #[test]
+    fn test_jump_quirk() {
+        let mut vm = VM::new();
+        vm.set_register(0, 1);
+        vm.set_register(3, 2);
+        vm.jump_location(0x300);
+        assert_eq!(vm.program_counter, 0x301);
+
+        vm.set_quirks(Platform::Schip.quirks());
+        vm.jump_location(0x300);
+        assert_eq!(vm.program_counter, 0x302);
+    }
+
+    #[test]
+    fn test_wrap_quirk() {
+        let mut vm = VM::new();
+        vm.set_index(0); // font sprite "0", top row 0xF0
+        vm.set_register(0, 62);
+        vm.draw(0, 1, 1).unwrap();
+        assert!(vm.pixel(0, 0));
+
+        vm.clear_display();
+        vm.set_quirks(Platform::Chip8.quirks());
+        vm.draw(0, 1, 1).unwrap();
+        assert!(vm.pixel(63, 0));
+        assert!(!vm.pixel(0, 0));
+    }
+
+    #[test]
+    fn test_logic_quirk() {
+        let mut vm = VM::new();
+        vm.set_carry_flag(1);
+        vm.reg_or(0, 1);
+        assert_eq!(vm.reg[0xF], 1);
+
+        vm.set_quirks(Platform::Chip8.quirks());
+        vm.reg_xor(0, 1);
+        assert_eq!(vm.reg[0xF], 0);
+    }
+
+    #[test]
+    fn test_platform_from_str() {
+        assert_eq!("XO-CHIP".parse(), Ok(Platform::XoChip));
+        assert_eq!("superchip".parse(), Ok(Platform::Schip));
+        assert!("megachip".parse::<Platform>().is_err());
+    }
+}
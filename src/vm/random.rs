@@ -0,0 +1,42 @@
+// Random numbers for CXNN, kept per VM so that a seeded VM always replays the same
+// way and clones running side by side don't draw from each other's sequence.
+// SplitMix64, which is more than a game needs and cheap to copy.
+use crate::VM;
+
+pub(super) const DEFAULT_SEED: u64 = 0x0C8_C8C8;
+
+impl VM {
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    pub(crate) fn random_byte(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+
+    #[test]
+    fn test_seeded_random() {
+        let mut a = VM::new();
+        a.seed(42);
+        let mut b = a.clone();
+        let bytes: Vec<u8> = (0..64).map(|_| a.random_byte()).collect();
+        assert_eq!(bytes, (0..64).map(|_| b.random_byte()).collect::<Vec<u8>>());
+        assert!(bytes.windows(2).any(|w| w[0] != w[1]));
+
+        b.seed(43);
+        assert_ne!(a.random_byte(), b.random_byte());
+
+        // CXNN masks the byte
+        a.random(0, 0x0F);
+        assert!(a.register(0) <= 0x0F);
+    }
+}
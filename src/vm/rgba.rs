@@ -0,0 +1,104 @@
+// RGBA8 images of the display, row-major and 4 bytes per pixel, for frontends,
+// screenshots and recordings. The palette (and for the live display, the filter)
+// is applied here so a frontend only has to upload the bytes to a texture.
+use super::screen::to_rgb;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH, VM};
+
+pub const RGBA_LEN: usize = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4;
+
+// a 64x32 RGBA frame scaled up by whole pixels
+#[must_use]
+pub fn scale_rgba(rgba: &[u8], scale: u32) -> Vec<u8> {
+    let scale = scale.max(1) as usize;
+    let width = SCREEN_WIDTH as usize;
+    let mut out = Vec::with_capacity(rgba.len() * scale * scale);
+    for row in rgba.chunks_exact(width * 4) {
+        let mut line = Vec::with_capacity(row.len() * scale);
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..scale {
+                line.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            out.extend_from_slice(&line);
+        }
+    }
+    out
+}
+
+impl VM {
+    // framebuffer through the palette without any filter, exactly what the program drew
+    #[must_use]
+    pub fn rgba_frame(&self) -> Vec<u8> {
+        let [bg, fg] = [self.palette.background, self.palette.foreground].map(to_rgb);
+        let mut out = Vec::with_capacity(RGBA_LEN);
+        for y in 0..SCREEN_HEIGHT as u8 {
+            for x in 0..SCREEN_WIDTH as u8 {
+                let [r, g, b] = if self.pixel(x, y) { fg } else { bg };
+                out.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        out
+    }
+
+    // Brings the filtered display image up to date, rewriting only the rows whose
+    // intensity changed. Returns false when it is identical to the last call.
+    pub fn update_display_rgba(&mut self) -> bool {
+        let rows = self.update_intensity();
+        let width = SCREEN_WIDTH as usize;
+        for y in (0..SCREEN_HEIGHT as usize).filter(|y| rows & (1 << y) != 0) {
+            for x in 0..width {
+                let i = y * width + x;
+                let [r, g, b] = to_rgb(self.palette.shade(self.intensity[i]));
+                self.display[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+        rows != 0
+    }
+
+    // the display as of the last `update_display_rgba`
+    #[must_use]
+    pub fn display_rgba(&self) -> &[u8] {
+        &self.display
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{scale_rgba, RGBA_LEN};
+    use crate::{Filter, VM};
+
+    #[test]
+    fn test_rgba_frame() {
+        let mut vm = VM::new();
+        vm.set_pixel(1, 0);
+        let rgba = vm.rgba_frame();
+        assert_eq!(rgba.len(), RGBA_LEN);
+        assert_eq!(&rgba[..8], &[0, 0, 0, 255, 0, 227, 48, 255]);
+    }
+
+    #[test]
+    fn test_display_rgba_applies_filter() {
+        let mut vm = VM::new();
+        vm.set_filter(Filter::Phosphor { decay: 0.5 });
+        vm.set_pixel(0, 0);
+        assert!(vm.update_display_rgba());
+        vm.set_pixel(0, 0);
+        assert!(vm.update_display_rgba());
+        assert_eq!(&vm.display_rgba()[..4], &[0, 113, 24, 255]);
+        assert_eq!(vm.rgba_frame()[1], 0);
+    }
+
+    #[test]
+    fn test_scale_rgba() {
+        let mut vm = VM::new();
+        vm.set_pixel(0, 0);
+        let scaled = scale_rgba(&vm.rgba_frame(), 2);
+        assert_eq!(scaled.len(), RGBA_LEN * 4);
+        let pitch = 128 * 4;
+        assert_eq!(scaled[1], 227);
+        assert_eq!(scaled[4 + 1], 227);
+        assert_eq!(scaled[pitch + 4 + 1], 227);
+        assert_eq!(scaled[8 + 1], 0);
+    }
+}
@@ -1,45 +1,216 @@
-use crate::{PIXEL_HEIGHT, PIXEL_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::framebuffer::Row;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use macroquad::color::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    Integer,    // whole multiples of the framebuffer, crisp pixels
+    Fractional, // fill as much of the window as the aspect ratio allows
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    pub foreground: Color,
+}
+
+impl Palette {
+    // colour of a pixel lit at the given intensity, 0.0 is background and 1.0 is foreground
+    #[must_use]
+    pub fn shade(&self, intensity: f32) -> Color {
+        let (bg, fg) = (self.background, self.foreground);
+        Color::new(
+            bg.r + (fg.r - bg.r) * intensity,
+            bg.g + (fg.g - bg.g) * intensity,
+            bg.b + (fg.b - bg.b) * intensity,
+            1.0,
+        )
+    }
+}
+
+// 8-bit RGB triple for image encoders and terminals
+#[allow(clippy::cast_sign_loss)]
+pub(crate) fn to_rgb(color: Color) -> [u8; 3] {
+    [color.r, color.g, color.b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: macroquad::color::BLACK,
+            foreground: macroquad::color::GREEN,
+        }
+    }
+}
+
+// area of the window the framebuffer is drawn into, letterboxed and centred
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub pixel_size: f32,
+}
+
+impl Viewport {
+    #[must_use]
+    pub fn fit(window_width: f32, window_height: f32, scaling: Scaling) -> Self {
+        let scale_x = window_width / SCREEN_WIDTH as f32;
+        let scale_y = window_height / SCREEN_HEIGHT as f32;
+        let mut pixel_size = scale_x.min(scale_y);
+        if scaling == Scaling::Integer {
+            pixel_size = pixel_size.floor().max(1.0);
+        }
+
+        Self {
+            x: ((window_width - pixel_size * SCREEN_WIDTH as f32) / 2.0).max(0.0),
+            y: ((window_height - pixel_size * SCREEN_HEIGHT as f32) / 2.0).max(0.0),
+            pixel_size,
+        }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> f32 {
+        self.pixel_size * SCREEN_WIDTH as f32
+    }
+
+    #[must_use]
+    pub fn height(&self) -> f32 {
+        self.pixel_size * SCREEN_HEIGHT as f32
+    }
+}
 
 impl super::VM {
+    // flips one pixel, returning true if it was lit
     pub fn set_pixel(&mut self, x: u8, y: u8) -> bool {
-        let idx = x as usize + (SCREEN_WIDTH as usize * y as usize);
-        if self.screen[idx] {
-            self.screen[idx] = false;
-            true
-        } else {
-            self.screen[idx] = true;
-            false
+        let x = u32::from(x) % SCREEN_WIDTH;
+        self.screen.xor_row(y, 1 << (Row::BITS - 1 - x))
+    }
+
+    #[must_use]
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        self.screen.pixel(x, y)
+    }
+
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        self.scaling = scaling;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.stale_rows = u32::MAX;
+    }
+
+    #[must_use]
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.fullscreen = !self.fullscreen;
+        // SAFETY: the macroquad context is live anywhere inside `#[macroquad::main]`,
+        // which covers every caller: main before `run`, and input handling inside it.
+        // Headless tools must not call this.
+        unsafe {
+            macroquad::window::get_internal_gl()
+                .quad_context
+                .set_fullscreen(self.fullscreen);
         }
     }
 
-    pub fn draw_screen(&self) {
+    // The core renders the display to RGBA; it is only re-uploaded to the texture
+    // when the display, filter or palette changed, then scaled up with nearest filtering.
+    pub fn draw_screen(&mut self) {
         use macroquad::prelude::*;
-        clear_background(BLACK);
-        for (i, b) in self.screen.iter().enumerate() {
-            let x = i % SCREEN_WIDTH as usize;
-            let y = i / SCREEN_WIDTH as usize;
-            let (x, y) = world_to_screen(x, y);
-            if *b {
-                draw_rectangle(x, y, PIXEL_WIDTH, PIXEL_HEIGHT, GREEN);
+        if self.update_display_rgba() || self.texture.is_none() {
+            let image = Image {
+                bytes: self.display.clone(),
+                width: SCREEN_WIDTH as u16,
+                height: SCREEN_HEIGHT as u16,
+            };
+            if let Some(texture) = self.texture {
+                texture.update(&image);
+            } else {
+                let texture = Texture2D::from_image(&image);
+                texture.set_filter(FilterMode::Nearest);
+                self.texture = Some(texture);
             }
         }
+
+        clear_background(self.palette.background);
+        let viewport = Viewport::fit(screen_width(), screen_height(), self.scaling);
+        if let Some(texture) = self.texture {
+            let params = DrawTextureParams {
+                dest_size: Some(vec2(viewport.width(), viewport.height())),
+                ..Default::default()
+            };
+            draw_texture_ex(texture, viewport.x, viewport.y, WHITE, params);
+        }
     }
 
     pub fn set_screen_border(&mut self) {
-        for i in 0..SCREEN_WIDTH {
-            self.screen[i as usize] = true;
-            self.screen[((SCREEN_HEIGHT - 1) * SCREEN_WIDTH + i) as usize] = true;
-        }
-        for i in 0..SCREEN_HEIGHT {
-            self.screen[(i * SCREEN_WIDTH) as usize] = true;
-            self.screen[(i * SCREEN_WIDTH + SCREEN_WIDTH - 1) as usize] = true;
+        let edges: Row = 1 << (Row::BITS - 1) | 1 << (Row::BITS - SCREEN_WIDTH);
+        for y in 0..SCREEN_HEIGHT as u8 {
+            let row = self.screen.row(y);
+            self.screen.set_row(y, row | edges);
         }
+        self.screen.set_row(0, Row::MAX);
+        self.screen.set_row(SCREEN_HEIGHT as u8 - 1, Row::MAX);
     }
 }
 
-// world is 0,0 -> 64,32, screen is 0,0 ->
-fn world_to_screen(x: usize, y: usize) -> (f32, f32) {
-    let out_x = x as f32 * PIXEL_WIDTH;
-    let out_y = y as f32 * PIXEL_HEIGHT;
-    (out_x, out_y)
+#[cfg(test)]
+mod test {
+    use super::{Scaling, Viewport};
+
+    #[test]
+    fn test_viewport_default_window() {
+        let v = Viewport::fit(1024.0, 512.0, Scaling::Integer);
+        assert_eq!(
+            v,
+            Viewport {
+                x: 0.0,
+                y: 0.0,
+                pixel_size: 16.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_viewport_letterbox_integer() {
+        let v = Viewport::fit(1000.0, 700.0, Scaling::Integer);
+        assert_eq!(
+            v,
+            Viewport {
+                x: 20.0,
+                y: 110.0,
+                pixel_size: 15.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_viewport_letterbox_fractional() {
+        let v = Viewport::fit(1000.0, 700.0, Scaling::Fractional);
+        assert_eq!(
+            v,
+            Viewport {
+                x: 0.0,
+                y: 100.0,
+                pixel_size: 15.625
+            }
+        );
+    }
+
+    #[test]
+    fn test_viewport_tiny_window() {
+        let v = Viewport::fit(10.0, 10.0, Scaling::Integer);
+        assert_eq!(
+            v,
+            Viewport {
+                x: 0.0,
+                y: 0.0,
+                pixel_size: 1.0
+            }
+        );
+    }
 }
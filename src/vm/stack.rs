@@ -1,31 +1,32 @@
+use super::Fault;
 use crate::STACK_SIZE;
 
 impl super::VM {
-    #[allow(clippy::missing_panics_doc)]
     #[allow(clippy::cast_sign_loss)]
-
-    pub fn pop(&mut self) -> u16 {
-        assert!(self.stack_pointer > -1, "Stack empty, attempted top POP");
+    pub fn pop(&mut self) -> Result<u16, Fault> {
+        if self.stack_pointer < 0 {
+            return Err(Fault::StackUnderflow);
+        }
 
         self.stack_pointer -= 1;
-        self.stack[(self.stack_pointer + 1) as usize]
+        Ok(self.stack[(self.stack_pointer + 1) as usize])
     }
 
-    #[allow(clippy::missing_panics_doc)]
     #[allow(clippy::cast_sign_loss)]
     #[allow(clippy::cast_possible_wrap)]
-    pub fn push(&mut self, value: u16) {
-        assert!(
-            self.stack_pointer < STACK_SIZE as i8,
-            "Stack full, attempted to PUSH"
-        );
+    pub fn push(&mut self, value: u16) -> Result<(), Fault> {
+        if self.stack_pointer >= STACK_SIZE as i8 - 1 {
+            return Err(Fault::StackOverflow);
+        }
 
         self.stack_pointer += 1;
         self.stack[self.stack_pointer as usize] = value;
+        Ok(())
     }
 }
 #[cfg(test)]
 mod test {
+    use crate::vm::Fault;
     use crate::VM;
 
     #[test]
@@ -33,21 +34,22 @@ mod test {
         let mut vm = VM::new();
 
         for x in 0..16 {
-            vm.push(x);
+            vm.push(x).unwrap();
         }
 
         for x in (0..16).rev() {
-            assert_eq!(x, vm.pop());
+            assert_eq!(Ok(x), vm.pop());
         }
+        assert_eq!(vm.pop(), Err(Fault::StackUnderflow));
     }
 
     #[test]
-    #[should_panic]
     fn test_stack_overflow() {
         let mut vm = VM::new();
 
-        for x in 0..17 {
-            vm.push(x);
+        for x in 0..16 {
+            vm.push(x).unwrap();
         }
+        assert_eq!(vm.push(16), Err(Fault::StackOverflow));
     }
 }
@@ -1,20 +1,26 @@
 use crate::SOUND;
 use macroquad::audio::{play_sound, PlaySoundParams};
 impl super::VM {
-    pub fn run_timers(&mut self) {
-        if self.delay_timer != 0 {
-            self.delay_timer -= 1;
-        }
-        match (self.sound_timer == 0, self.sound_playing) {
-            (true, true) => {
+    // 60Hz tick without any audio side effects, for headless frontends
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    #[must_use]
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer != 0
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    pub fn update_sound(&mut self) {
+        match (self.sound_active(), self.sound_playing) {
+            (false, true) => {
                 // STOP SOUND
                 self.sound_playing = false;
                 macroquad::audio::stop_sound(*SOUND.get().expect("could not get sound"));
             }
             (true, false) => {
-                // NOP
-            }
-            (false, false) => {
                 // START SOUND
                 self.sound_playing = true;
                 play_sound(
@@ -24,11 +30,9 @@ impl super::VM {
                         volume: 0.2,
                     },
                 );
-                self.sound_timer -= 1;
             }
-            (false, true) => {
-                // CONTINUE SOUND
-                self.sound_timer -= 1;
+            (false, false) | (true, true) => {
+                // NOP / CONTINUE SOUND
             }
         }
     }
@@ -0,0 +1,198 @@
+use super::Fault;
+use crate::{OpCode, VM};
+
+// COSMAC VIP: 1.7609 MHz clock, 8 clocks per 1802 machine cycle, 60Hz display interrupt
+const VIP_CYCLES_PER_FRAME: i64 = 3668;
+// display DMA (32 lines x 4 scans x 8 bytes) plus the interrupt routine that decrements
+// the timers, both stolen from the interpreter every frame (approximate)
+const VIP_INTERRUPT_CYCLES: i64 = 1024 + 98;
+// interpreter main loop: fetch two bytes, advance PC and dispatch on the high nibble
+const VIP_FETCH_CYCLES: i64 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Fixed(u32), // instructions per 60Hz frame, every opcode costs the same
+    Vip,        // per-opcode machine cycles of the VIP interpreter, DXYN waits for vblank
+}
+
+impl VM {
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycle_budget = 0;
+    }
+
+    // Runs one 60Hz frame worth of instructions then ticks the timers, as the
+    // VIP's display interrupt would at the end of every frame. On a fault the
+    // timers are left alone and PC points at the faulting instruction.
+    pub fn run_frame(&mut self) -> Result<(), Fault> {
+        self.run_frame_until(|_| false).map(|_| ())
+    }
+
+    // Like `run_frame`, but asks `stop` before every instruction and ends the frame
+    // early, without ticking the timers, when it returns true. Returns whether it
+    // stopped. Debuggers use this for breakpoints, scripts to hook instructions.
+    pub fn run_frame_until(
+        &mut self,
+        mut stop: impl FnMut(&mut VM) -> bool,
+    ) -> Result<bool, Fault> {
+        match self.timing {
+            Timing::Fixed(instructions) => {
+                for _ in 0..instructions {
+                    if stop(self) {
+                        return Ok(true);
+                    }
+                    let op = self.step()?;
+                    if self.quirks.vblank && matches!(op, OpCode::DRW { .. }) {
+                        break;
+                    }
+                }
+            }
+            Timing::Vip => {
+                self.cycle_budget += VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+                while self.cycle_budget > 0 {
+                    if stop(self) {
+                        // the unspent budget carries over to the next call
+                        self.cycle_budget -= VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+                        return Ok(true);
+                    }
+                    let pc = self.program_counter;
+                    let op = OpCode::from_bytes(self.get_instruction()?);
+                    let mut cost = VIP_FETCH_CYCLES + self.vip_cycles(op);
+                    if let Err(fault) = self.execute_op(&op) {
+                        self.program_counter = pc;
+                        return Err(fault);
+                    }
+                    if is_skip(op) && self.program_counter == pc.wrapping_add(4) {
+                        cost += 4;
+                    }
+                    if matches!(op, OpCode::DRW { .. }) {
+                        // the sprite is drawn once vblank arrives, so the rest of this
+                        // frame is lost and the drawing itself comes out of the next one
+                        self.cycle_budget = -cost;
+                        break;
+                    }
+                    self.cycle_budget -= cost;
+                }
+            }
+        }
+        self.tick_timers();
+        self.apply_cheats();
+        self.profile_frame();
+        Ok(false)
+    }
+
+    // Approximate execution cost in machine cycles, excluding the fetch, after
+    // Laurence Scotford's analysis of the VIP CHIP-8 interpreter.
+    fn vip_cycles(&self, op: OpCode) -> i64 {
+        use OpCode::{
+            Unknown, ADD, ADDI, CALL, CLS, DRW, JMP, JP, KPR, LD, LDSPR, LDT, RADD, RAND, READ,
+            RET, RLD, RND, ROR, RSE, RSHL, RSHR, RSNE, RSUB, RSUBN, RXOR, SE, SET, SETDT, SETST,
+            SKNP, SKP, SNE, STBCD, STORE,
+        };
+
+        match op {
+            CLS => 3078,
+            RET | SE { .. } | SNE { .. } | ADD { .. } | LDT(_) | SETDT(_) | SETST(_) => 10,
+            JMP(_) | LD(_) => 12,
+            CALL(_) => 26,
+            RSE { .. } | RSNE { .. } | SKP(_) | SKNP(_) => 14,
+            SET { .. } => 6,
+            RLD { .. }
+            | ROR { .. }
+            | RAND { .. }
+            | RXOR { .. }
+            | RADD { .. }
+            | RSUB { .. }
+            | RSHR { .. }
+            | RSUBN { .. }
+            | RSHL { .. } => 44,
+            JP(_) => 22,
+            RND { .. } => 36,
+            DRW { x, n, .. } => {
+                // every sprite row is shifted right bit by bit into position
+                let shift = i64::from(self.reg[x as usize] % 8);
+                26 + i64::from(n) * (46 + 20 * shift)
+            }
+            KPR(_) => 20,
+            ADDI(_) | LDSPR(_) => 16,
+            STBCD(x) => {
+                // digits are found by repeated subtraction
+                let v = self.reg[x as usize];
+                80 + 16 * i64::from(v / 100 + (v / 10) % 10 + v % 10)
+            }
+            STORE(x) | READ(x) => 14 + 14 * (i64::from(x) + 1),
+            Unknown(_) => 0,
+        }
+    }
+}
+
+fn is_skip(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::SE { .. }
+            | OpCode::SNE { .. }
+            | OpCode::RSE { .. }
+            | OpCode::RSNE { .. }
+            | OpCode::SKP(_)
+            | OpCode::SKNP(_)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timing;
+    use crate::VM;
+
+    #[test]
+    fn test_fixed_timing() {
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x70, 0x01, 0x12, 0x00], 0x200).unwrap(); // ADD V0, 1; JMP 0x200
+        vm.set_timing(Timing::Fixed(10));
+        vm.run_frame().unwrap();
+        assert_eq!(vm.reg[0], 5);
+    }
+
+    #[test]
+    fn test_run_frame_until_stops_before_instruction() {
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x70, 0x01, 0x12, 0x00], 0x200).unwrap();
+        vm.set_timing(Timing::Fixed(10));
+        vm.delay_timer = 1;
+        let mut seen = 0;
+        let stopped = vm
+            .run_frame_until(|vm| {
+                seen += 1;
+                vm.program_counter() == 0x200 && vm.reg[0] == 2
+            })
+            .unwrap();
+        assert!(stopped);
+        assert_eq!((seen, vm.reg[0], vm.delay_timer), (5, 2, 1));
+        assert!(!vm.run_frame_until(|_| false).unwrap());
+        assert_eq!(vm.delay_timer, 0);
+    }
+
+    #[test]
+    fn test_vip_timing_counts_cycles() {
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x70, 0x01, 0x12, 0x00], 0x200).unwrap();
+        vm.set_timing(Timing::Vip);
+        vm.run_frame().unwrap();
+        // (3668 - 1122) / ((40 + 10) + (40 + 12)) loop iterations, rounding up
+        assert_eq!(vm.reg[0], 25);
+    }
+
+    #[test]
+    fn test_vip_draw_waits_for_vblank() {
+        let mut vm = VM::new();
+        // ADD V0, 1; DRW V1, V1, 1; JMP 0x200
+        vm.load_bytes(&[0x70, 0x01, 0xD1, 0x11, 0x12, 0x00], 0x200)
+            .unwrap();
+        vm.set_timing(Timing::Vip);
+        vm.delay_timer = 3;
+        for _ in 0..3 {
+            vm.run_frame().unwrap();
+        }
+        assert_eq!(vm.reg[0], 3);
+        assert_eq!(vm.delay_timer, 0);
+    }
+}
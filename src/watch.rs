@@ -0,0 +1,245 @@
+// Watch mode: reloads the ROM whenever the file changes, so a game can be rebuilt
+// without restarting the emulator. Given a build command, the game's source (the
+// `.8o` next to the ROM by default) is watched too and the command runs when it
+// changes, and its new ROM is then picked up like any other:
+//
+//     chip8 --watch --build "octo game.8o game.ch8" game.ch8
+//
+// Files are compared by modification time, and a change is only acted on once it
+// has held for two polls, so a ROM caught half written isn't loaded.
+use crate::config::{Config, ConfigLayer};
+use crate::patch;
+use crate::VM;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::SystemTime;
+
+// what a poll did, each with a message for the player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    Reloaded(String), // the ROM's file name
+    Building(String), // the source that changed
+    Failed(String),   // why a build or reload didn't happen
+}
+
+impl fmt::Display for WatchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchEvent::Reloaded(name) => write!(f, "Reloaded {name}"),
+            WatchEvent::Building(source) => write!(f, "Rebuilding {source}"),
+            WatchEvent::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>, // as last acted on
+    seen: Option<SystemTime>,     // as of the last poll
+}
+
+impl Watched {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self {
+            path,
+            modified,
+            seen: modified,
+        }
+    }
+
+    // true once a change has settled
+    fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        let settled = now == self.seen && now != self.modified && now.is_some();
+        self.seen = now;
+        if settled {
+            self.modified = now;
+        }
+        settled
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    shell.arg(command);
+    shell
+}
+
+#[derive(Debug)]
+pub struct Watch {
+    rom: Watched,
+    patches: Vec<PathBuf>,
+    cli: ConfigLayer,
+    keep_state: bool,
+    source: Option<Watched>,
+    build: Option<String>,
+    building: Option<Child>,
+}
+
+impl Watch {
+    // `patches` and `cli` as the ROM was first loaded with
+    #[must_use]
+    pub fn new(rom_path: &Path, patches: &[PathBuf], cli: ConfigLayer) -> Self {
+        Self {
+            rom: Watched::new(rom_path.to_path_buf()),
+            patches: patches.to_vec(),
+            cli,
+            keep_state: false,
+            source: None,
+            build: None,
+            building: None,
+        }
+    }
+
+    // keep registers and memory outside the program on reload, rather than reset
+    #[must_use]
+    pub fn keep_state(mut self, keep: bool) -> Self {
+        self.keep_state = keep;
+        self
+    }
+
+    // runs `command` when `source`, or the `.8o` beside the ROM, changes
+    #[must_use]
+    pub fn build(mut self, command: &str, source: Option<&Path>) -> Self {
+        let source = source.map_or_else(|| self.rom.path.with_extension("8o"), Path::to_path_buf);
+        self.source = Some(Watched::new(source));
+        self.build = Some(command.to_string());
+        self
+    }
+
+    // Checks the files, rebuilding or reloading `vm` if they changed, and says what
+    // happened. Meant to be called a few times a second.
+    pub fn poll(&mut self, vm: &mut VM) -> Option<WatchEvent> {
+        if let Some(child) = &mut self.building {
+            match child.try_wait() {
+                Ok(None) => return None,
+                Ok(Some(status)) if status.success() => self.building = None,
+                Ok(Some(status)) => {
+                    self.building = None;
+                    return Some(WatchEvent::Failed(format!("Build failed ({status})")));
+                }
+                Err(e) => {
+                    self.building = None;
+                    return Some(WatchEvent::Failed(format!("Build failed: {e}")));
+                }
+            }
+        }
+
+        if let (Some(source), Some(build)) = (&mut self.source, &self.build) {
+            if source.changed() {
+                return Some(match shell(build).spawn() {
+                    Ok(child) => {
+                        self.building = Some(child);
+                        WatchEvent::Building(source.path.display().to_string())
+                    }
+                    Err(e) => WatchEvent::Failed(format!("Build failed: {e}")),
+                });
+            }
+        }
+
+        if !self.rom.changed() {
+            return None;
+        }
+        let path = &self.rom.path;
+        let reload = patch::load(path, &self.patches).and_then(|rom| {
+            let config = Config::resolve(&rom, path, &self.cli)?;
+            vm.reload(&config, &rom, self.keep_state)
+        });
+        Some(match reload {
+            Ok(()) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                WatchEvent::Reloaded(name.to_string())
+            }
+            Err(e) => WatchEvent::Failed(format!("Reload failed: {e}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Watch, WatchEvent};
+    use crate::config::ConfigLayer;
+    use crate::VM;
+    use std::time::{Duration, SystemTime};
+
+    fn touch(path: &std::path::Path, bytes: &[u8], age: u64) {
+        std::fs::write(path, bytes).unwrap();
+        let time = SystemTime::now() - Duration::from_secs(age);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let dir = std::env::temp_dir().join(format!("chip8-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        touch(&rom, &[0x60, 0x05, 0x12, 0x02], 60);
+
+        let mut vm = VM::new();
+        vm.load_bytes(&[0x60, 0x05, 0x12, 0x02], 0x200).unwrap();
+        vm.run_frame().unwrap();
+        let mut watch = Watch::new(&rom, &[], ConfigLayer::default()).keep_state(true);
+        assert_eq!(watch.poll(&mut vm), None);
+
+        touch(&rom, &[0x61, 0x07, 0x12, 0x02], 30);
+        assert_eq!(watch.poll(&mut vm), None); // not settled yet
+        assert_eq!(
+            watch.poll(&mut vm),
+            Some(WatchEvent::Reloaded("game.ch8".to_string()))
+        );
+        assert_eq!(vm.read_memory(0x200, 2), &[0x61, 0x07]);
+        assert_eq!(vm.register(0), 5);
+        assert_eq!(watch.poll(&mut vm), None);
+
+        // the build runs when the source changes, and a failure is reported
+        let source = dir.join("game.8o");
+        touch(&source, b"", 60);
+        let mut watch = Watch::new(&rom, &[], ConfigLayer::default()).build("exit 3", None);
+        touch(&source, b": main", 30);
+        assert_eq!(watch.poll(&mut vm), None);
+        assert!(matches!(watch.poll(&mut vm), Some(WatchEvent::Building(_))));
+        let mut message = None;
+        for _ in 0..100 {
+            message = watch.poll(&mut vm);
+            if message.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let Some(WatchEvent::Failed(message)) = message else {
+            panic!("expected the build to fail, got {message:?}");
+        };
+        assert!(message.starts_with("Build failed"));
+
+        // a rebuilt ROM too large for memory is reported, not loaded
+        let mut watch = Watch::new(&rom, &[], ConfigLayer::default());
+        touch(&rom, &[0; 0x1000], 10);
+        assert_eq!(watch.poll(&mut vm), None);
+        let Some(WatchEvent::Failed(message)) = watch.poll(&mut vm) else {
+            panic!("expected the reload to fail");
+        };
+        assert!(message.contains("ROM larger than memory"), "{message}");
+        assert_eq!(vm.read_memory(0x200, 2), &[0x61, 0x07]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}